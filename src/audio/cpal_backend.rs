@@ -1,21 +1,32 @@
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::{SampleFormat, Stream};
-use std::sync::{Arc, Mutex};
 use crate::audio::AudioBackend;
 use crate::synth::engine::SynthEngine;
+use crate::synth::realtime::{EngineController, RealtimeEngine};
 
 pub struct CpalBackend {
     stream: Option<Stream>,
-    synth_engine: Arc<Mutex<SynthEngine>>,
+    engine: Option<RealtimeEngine>,       // Moved into the audio callback when the stream is built
+    controller: Option<EngineController>, // Controller of the engine made by `new`, until taken
 }
 
 impl CpalBackend {
-    pub fn new_with_engine(synth_engine: Arc<Mutex<SynthEngine>>) -> Self {
+    /// The audio callback takes ownership of `engine`; control it through the
+    /// `EngineController` that came with it.
+    pub fn new_with_engine(engine: RealtimeEngine) -> Self {
         Self {
             stream: None,
-            synth_engine,
+            engine: Some(engine),
+            controller: None,
         }
     }
+
+    /// The controller of the engine `AudioBackend::new` created, for the thread that plays
+    /// it. None once taken, or when the engine came from `new_with_engine`.
+    pub fn take_controller(&mut self) -> Option<EngineController> {
+        self.controller.take()
+    }
+
     fn determine_buffer_size(&self, device: &cpal::Device, config: cpal::SupportedStreamConfig) -> Result<usize, Box<dyn std::error::Error>> {
         let channels = config.channels() as usize;
 
@@ -95,28 +106,18 @@ impl CpalBackend {
         let channels = config.channels() as usize;
         let buffer_size = self.determine_buffer_size(&device, buffer_config)?;
 
-        {
-            let mut synth_engine = self.synth_engine.lock().unwrap();
-            synth_engine.set_buffer_size(buffer_size);
-        }
+        let mut engine = self.engine.take().ok_or("The engine already belongs to a stream")?;
+        engine.engine_mut().set_buffer_size(buffer_size);
 
         let sample_rate = config.sample_rate().0;
         let channels = config.channels() as usize;
-        let synth_engine = self.synth_engine.clone();
 
         let stream = match config.sample_format() {
             SampleFormat::F32 => device.build_output_stream(
                 &config.into(),
                 move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-                    let mut synth_engine = synth_engine.lock().unwrap();
-                    let mut buffer = vec![0.0; data.len() / channels];
-                    synth_engine.process(&mut buffer, sample_rate as f32);
-
-                    for (i, frame) in data.chunks_mut(channels).enumerate() {
-                        for sample in frame.iter_mut() {
-                            *sample = buffer[i];
-                        }
-                    }
+                    // The engine writes left/right into the first two channels of each frame
+                    engine.process_interleaved(data, channels, sample_rate as f32);
                 },
                 |err| eprintln!("an error occurred on stream: {}", err),
                 None,
@@ -130,13 +131,19 @@ impl CpalBackend {
 
 impl AudioBackend for CpalBackend {
     fn new() -> Self {
+        let (engine, controller) = RealtimeEngine::new(SynthEngine::new());
         Self {
-            stream: None,
-            synth_engine: Arc::new(Mutex::new(SynthEngine::new())),
+            controller: Some(controller),
+            ..Self::new_with_engine(engine)
         }
     }
 
     fn start(&mut self) {
+        // A stopped stream still owns the engine, so resume it rather than building another
+        if let Some(stream) = &self.stream {
+            stream.play().expect("Failed to play stream");
+            return;
+        }
         if let Ok(stream) = self.build_stream() {
             stream.play().expect("Failed to play stream");
             self.stream = Some(stream);
@@ -150,7 +157,10 @@ impl AudioBackend for CpalBackend {
     }
 
     fn process_audio(&mut self, output: &mut [f32]) {
-        let mut synth_engine = self.synth_engine.lock().unwrap();
-        synth_engine.process(output, 44100.0);
+        // Only possible before the stream takes the engine
+        match &mut self.engine {
+            Some(engine) => engine.process_interleaved(output, 1, 44100.0),
+            None => output.fill(0.0),
+        }
     }
 }
@@ -0,0 +1,170 @@
+//! Offline renderer: plays a MIDI file or note list through the synth and writes a WAV file.
+//! Runs faster than real time with no audio device, and the same inputs always give the
+//! same output, which makes it suitable for regression checks and batch rendering.
+
+use rustfmsynth::synth::engine::SynthEngine;
+use rustfmsynth::synth::patch::load_patch;
+use rustfmsynth::synth::sequencer::Sequencer;
+use rustfmsynth::utils::midi_file::read_midi_file;
+use rustfmsynth::utils::note_list::read_note_list;
+use rustfmsynth::utils::wav::{write_wav, WavData, WavSampleFormat};
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: render (--midi FILE | --notes FILE) -o OUTPUT.wav [options]
+
+Inputs:
+  --midi FILE          Standard MIDI File (type 0 or 1)
+  --notes FILE         Note list: one `start duration note [velocity]` per line, in seconds
+  --patch FILE         Patch to load before rendering
+
+Options:
+  -o, --output FILE    WAV file to write
+  --sample-rate HZ     Output sample rate (default 44100)
+  --format FORMAT      16, 24 or 32f (default 16)
+  --channels N         1 or 2 (default 2)
+  --tail SECONDS       Longest time notes may ring on after the last event (default 5)
+  --seed N             Noise seed, for repeatable noise (default 0)
+  --block FRAMES       Frames rendered per engine call (default 256)";
+
+struct Options {
+    midi: Option<String>,
+    notes: Option<String>,
+    patch: Option<String>,
+    output: String,
+    sample_rate: u32,
+    format: WavSampleFormat,
+    channels: u16,
+    tail: f32,
+    seed: u64,
+    block: usize,
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    match parse_args(&args).and_then(|options| render(&options)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}\n\n{}", e, USAGE);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        midi: None,
+        notes: None,
+        patch: None,
+        output: String::new(),
+        sample_rate: 44100,
+        format: WavSampleFormat::Pcm16,
+        channels: 2,
+        tail: 5.0,
+        seed: 0,
+        block: 256,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("{} needs a value.", arg))
+        };
+        match arg.as_str() {
+            "--midi" => options.midi = Some(value()?),
+            "--notes" => options.notes = Some(value()?),
+            "--patch" => options.patch = Some(value()?),
+            "-o" | "--output" => options.output = value()?,
+            "--sample-rate" => options.sample_rate = parse_value(arg, &value()?)?,
+            "--format" => {
+                options.format = match value()?.as_str() {
+                    "16" => WavSampleFormat::Pcm16,
+                    "24" => WavSampleFormat::Pcm24,
+                    "32f" => WavSampleFormat::Float32,
+                    other => return Err(format!("Unknown format `{}`.", other)),
+                }
+            }
+            "--channels" => options.channels = parse_value(arg, &value()?)?,
+            "--tail" => options.tail = parse_value(arg, &value()?)?,
+            "--seed" => options.seed = parse_value(arg, &value()?)?,
+            "--block" => options.block = parse_value(arg, &value()?)?,
+            _ => return Err(format!("Unknown argument `{}`.", arg)),
+        }
+    }
+
+    if options.midi.is_some() == options.notes.is_some() {
+        return Err("Give exactly one of --midi or --notes.".to_string());
+    }
+    if options.output.is_empty() {
+        return Err("No output file given.".to_string());
+    }
+    if !(1..=2).contains(&options.channels) {
+        return Err("--channels must be 1 or 2.".to_string());
+    }
+    if options.sample_rate == 0 || options.block == 0 {
+        return Err("--sample-rate and --block must be above zero.".to_string());
+    }
+    Ok(options)
+}
+
+fn parse_value<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value `{}` for {}.", value, name))
+}
+
+fn render(options: &Options) -> Result<(), String> {
+    let file = match (&options.midi, &options.notes) {
+        (Some(path), _) => read_midi_file(path)?,
+        (_, Some(path)) => read_note_list(path)?,
+        _ => unreachable!("checked in parse_args"),
+    };
+
+    let mut engine = SynthEngine::new();
+    engine.set_noise_seed(options.seed);
+    if let Some(path) = &options.patch {
+        load_patch(&mut engine, path)?;
+    }
+    engine.set_sequencer(Some(Sequencer::new(&file)));
+
+    let channels = options.channels as usize;
+    let sample_rate = options.sample_rate as f32;
+    let max_tail = (options.tail.max(0.0) * sample_rate) as usize;
+    let mut buffer = vec![0.0; options.block * channels];
+    let mut samples = Vec::new();
+    let mut tail = 0;
+    loop {
+        engine.process_interleaved(&mut buffer, channels, sample_rate);
+        samples.extend_from_slice(&buffer);
+
+        // Once the file is over, carry on until every voice has died away (or the tail runs out)
+        let sequence_finished = engine
+            .sequencer_mut()
+            .is_none_or(|sequencer| sequencer.is_finished());
+        if sequence_finished {
+            if engine.voices.iter().all(|voice| voice.is_finished()) || tail >= max_tail {
+                break;
+            }
+            tail += options.block;
+        }
+    }
+
+    let data = WavData {
+        sample_rate: options.sample_rate,
+        channels: options.channels,
+        samples,
+    };
+    write_wav(&options.output, &data, options.format)?;
+    eprintln!(
+        "Wrote {:.2} s to {}",
+        data.samples.len() as f32 / channels as f32 / sample_rate,
+        options.output
+    );
+    Ok(())
+}
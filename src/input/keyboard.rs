@@ -1,6 +1,6 @@
-use crate::synth::engine::SynthEngine;
 use crate::synth::note::{NoteEvent, NoteSource};
 use crate::synth::operator::{CycleDirection, OperatorEvent};
+use crate::synth::realtime::EngineController;
 use device_query::{DeviceQuery, DeviceState, Keycode};
 use std::collections::HashMap;
 
@@ -16,10 +16,8 @@ impl KeyboardHandler {
         Self::default()
     }
 
-    pub fn update(&mut self, engine: &mut SynthEngine) {
+    pub fn update(&mut self, controller: &mut EngineController) {
         let keys: Vec<Keycode> = self.device_state.get_keys();
-        let note_sender = engine.get_note_sender();
-        let operator_sender = engine.get_operator_sender();
 
         // Check each mapped key for notes
         for (key, note) in &self.key_to_note {
@@ -33,8 +31,8 @@ impl KeyboardHandler {
                         key, note
                     );
                     if let Ok(event) = NoteEvent::new(*note, 100, true, NoteSource::Keyboard) {
-                        if let Err(e) = note_sender.send(event) {
-                            eprintln!("Error sending note on event: {}", e);
+                        if controller.send(event).is_err() {
+                            eprintln!("Error sending note on event: event queue is full");
                         }
                     }
                 } else {
@@ -43,8 +41,8 @@ impl KeyboardHandler {
                         key, note
                     );
                     if let Ok(event) = NoteEvent::new(*note, 0, false, NoteSource::Keyboard) {
-                        if let Err(e) = note_sender.send(event) {
-                            eprintln!("Error sending note off event: {}", e);
+                        if controller.send(event).is_err() {
+                            eprintln!("Error sending note off event: event queue is full");
                         }
                     }
                 }
@@ -62,18 +60,20 @@ impl KeyboardHandler {
                 match key {
                     Keycode::Comma => {
                         println!("Cycling waveform backward");
-                        if let Err(e) = operator_sender.send(OperatorEvent::CycleWaveform {
+                        let event = OperatorEvent::CycleWaveform {
                             direction: CycleDirection::Backward,
-                        }) {
-                            eprintln!("Error sending operator event: {}", e);
+                        };
+                        if controller.send(event).is_err() {
+                            eprintln!("Error sending operator event: event queue is full");
                         }
                     }
                     Keycode::Dot => {
                         println!("Cycling waveform forward");
-                        if let Err(e) = operator_sender.send(OperatorEvent::CycleWaveform {
+                        let event = OperatorEvent::CycleWaveform {
                             direction: CycleDirection::Forward,
-                        }) {
-                            eprintln!("Error sending operator event: {}", e);
+                        };
+                        if controller.send(event).is_err() {
+                            eprintln!("Error sending operator event: event queue is full");
                         }
                     }
                     _ => {}
@@ -1,4 +1,6 @@
+#[cfg(feature = "realtime")]
 pub mod audio;
 pub mod synth;
 pub mod utils;
+#[cfg(feature = "realtime")]
 pub mod input;
@@ -1,14 +1,14 @@
-use std::sync::{Arc, Mutex};
 use rustfmsynth::audio::{AudioBackend, CpalBackend};
 use rustfmsynth::synth::engine::SynthEngine;
+use rustfmsynth::synth::realtime::RealtimeEngine;
 use rustfmsynth::input::KeyboardHandler;
 
 fn main() {
-    // Create a shared synth engine
-    let synth_engine = Arc::new(Mutex::new(SynthEngine::new()));
+    // The audio thread owns the engine; this thread controls it through a lock-free queue
+    let (engine, mut controller) = RealtimeEngine::new(SynthEngine::new());
 
     // Create and start audio backend
-    let mut audio_backend = CpalBackend::new_with_engine(synth_engine.clone());
+    let mut audio_backend = CpalBackend::new_with_engine(engine);
     audio_backend.start();
 
     // Set up keyboard input
@@ -16,14 +16,8 @@ fn main() {
 
     // Main loop for keyboard handling
     loop {
-        // Lock the synth engine once per frame
-        let mut engine = synth_engine.lock().unwrap();
-        
         // Update keyboard state and send note events
-        keyboard_handler.update(&mut *engine);
-        
-        // Release the lock
-        drop(engine);
+        keyboard_handler.update(&mut controller);
 
         std::thread::sleep(std::time::Duration::from_millis(10));
     }
@@ -1,54 +1,172 @@
-use super::operator::Operator; // Assuming Operator is defined in a parent module
+use super::modulation::VoiceModulation;
+use super::operator::{Operator, OperatorState}; // Assuming Operator is defined in a parent module
+use super::pan::pan_gains;
 use std::collections::HashMap;
 
-// --- Internal Graph Structures (Used by Algorithm::process) ---
+/// How connections that close a modulation loop (`Some(N)` with N >= 2) are evaluated.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum FeedbackMode {
+    // The loop is unrolled N - 1 levels deep, and each level runs over the whole buffer
+    #[default]
+    Unrolled,
+    // DX7-style: the operators in a loop run one sample at a time, and each feedback
+    // connection carries the average of its modulator's last two samples, scaled by the
+    // modulator's `Operator::feedback`
+    SingleSample,
+}
+
+// --- Internal Graph Structures (Used by Algorithm::new) ---
 
-/// Represents an Operator at a specific 'unrolled' feedback level in the DAG.
-#[derive(Debug)]
-struct UnrolledNode {
+/// An operator in the graph a schedule is compiled from. When unrolling feedback, there is
+/// one node for every feedback level the operator appears at.
+struct GraphNode {
     original_op_index: usize, // Index into the original operators array
-    // Indices of required input nodes within the `AlgorithmProcessor::nodes` vector.
+    // Indices of the nodes modulating this one within the same sample.
     input_node_indices: Vec<usize>,
+    // Indices of the nodes modulating this one one sample late (single-sample feedback only).
+    feedback_node_indices: Vec<usize>,
+}
+
+/// The nodes and the order to run them in.
+struct Graph {
+    nodes: Vec<GraphNode>,
+    carrier_nodes: Vec<usize>, // Node of each carrier
+    // Nodes in run order. A group of several nodes, or of one with feedback, is a loop and
+    // runs one sample at a time.
+    groups: Vec<Vec<usize>>,
 }
 
-/// Holds the pre-built DAG and operator references for processing.
-struct AlgorithmProcessor<'a> {
-    nodes: Vec<UnrolledNode>,
-    operators: &'a [Operator],
-    // Indices in `self.nodes` corresponding to the final output of carrier operators.
-    carrier_node_indices: Vec<usize>,
+/// Progress of the depth-first walk that orders the nodes.
+#[derive(Clone, Copy, PartialEq)]
+enum Visit {
+    New,
+    InProgress, // Reaching a node again while it is here means a loop with no feedback delay
+    Done,
+}
+
+// --- Compiled Schedule ---
+
+/// The algorithm compiled into steps that every voice runs in order.
+#[derive(Clone, Debug, Default)]
+struct Schedule {
+    steps: Vec<Step>,
+    operator_count: usize,         // Operators the schedule was compiled for
+    carrier_outputs: Vec<SlotRef>, // Slots holding the carriers' output at the end
+    slot_count: usize,             // Buffer slots the steps use
+    loop_size: usize,              // Most operators in one loop step
+}
+
+/// One step of the compiled schedule.
+#[derive(Clone, Debug)]
+enum Step {
+    Block(StepOperator),     // Run one operator over the whole buffer
+    Loop(Vec<StepOperator>), // Run the operators of a feedback loop one sample at a time
+}
+
+/// An operator run by a step, and where its inputs and output live.
+#[derive(Clone, Debug)]
+struct StepOperator {
+    operator: usize,          // Operator to run
+    modulators: Vec<SlotRef>, // Outputs summed into its modulation input
+    feedback: Vec<SlotRef>,   // Outputs fed back one sample late; only in loop steps
+    output: usize,            // Slot its output goes to
+}
+
+/// An operator's output held in a buffer slot.
+#[derive(Clone, Copy, Debug)]
+struct SlotRef {
+    operator: usize, // Operator that produced it, for its modulation index and pan
+    slot: usize,
+}
+
+/// Per-voice scratch memory for `Algorithm::process`, kept between buffers so processing
+/// doesn't allocate. Grows if an algorithm needs more than it was prepared for.
+#[derive(Clone, Debug, Default)]
+pub struct AlgorithmBuffers {
+    slots: Vec<Vec<f32>>, // Operator outputs, as assigned by the schedule
+    input: Vec<f32>,      // Summed modulation into the operator being run
+    // Per-voice operator state as it was at the start of the buffer. An operator can appear in
+    // several unrolled nodes, so every node starts from this snapshot instead of advancing it twice.
+    start_states: Vec<OperatorState>,
+    phase_increments: Vec<f32>, // Scratch for `Operator::process`
+    // Phase steps and levels of each operator in the loop being run
+    loop_increments: Vec<Vec<f32>>,
+    loop_levels: Vec<Vec<f32>>,
+}
+
+impl AlgorithmBuffers {
+    /// Reserves what `algorithm` needs to process buffers of up to `max_block_size` samples.
+    pub fn reserve(&mut self, algorithm: &Algorithm, max_block_size: usize) {
+        let schedule = &algorithm.schedule;
+        self.slots
+            .resize_with(schedule.slot_count.max(self.slots.len()), Vec::new);
+        for buffers in [&mut self.loop_increments, &mut self.loop_levels] {
+            buffers.resize_with(schedule.loop_size.max(buffers.len()), Vec::new);
+        }
+        for buffer in self
+            .slots
+            .iter_mut()
+            .chain(self.loop_increments.iter_mut())
+            .chain(self.loop_levels.iter_mut())
+            .chain([&mut self.input, &mut self.phase_increments])
+        {
+            buffer.reserve(max_block_size.saturating_sub(buffer.len()));
+        }
+        self.start_states.reserve(algorithm.operator_count());
+    }
 }
 
 // --- Public Algorithm Struct (Matches Original API) ---
 
 /// Defines the operator connections and processing logic for FM synthesis.
+/// The connections are compiled once, into a flat schedule that every voice runs in order.
 #[derive(Clone, Debug)]
 pub struct Algorithm {
     /// Adjacency matrix: `matrix[i][j] = Some(N)` means op `j` modulates op `i`.
+    /// Changes to `matrix` and `carriers` take effect once the algorithm is recompiled.
     pub matrix: Vec<Vec<Option<usize>>>,
     pub carriers: Vec<usize>,
+    feedback_mode: FeedbackMode,
+    schedule: Schedule,
 }
 
 // --- Implementation ---
 
 impl Algorithm {
-    /// Creates a new algorithm definition.
+    /// Creates a new algorithm definition, with unrolled feedback.
     pub fn new(matrix: Vec<Vec<Option<usize>>>, carriers: Vec<usize>) -> Result<Self, String> {
-        let num_ops = matrix.len();
-        if num_ops > 0 && !matrix.iter().all(|row| row.len() == num_ops) {
-            return Err("Adjacency matrix must be square.".to_string());
-        }
-        if let Some(max_carrier) = carriers.iter().max() {
-            if num_ops == 0 || *max_carrier >= num_ops {
-                // Also check num_ops > 0 for max_carrier check
-                return Err(format!(
-                    "Carrier index {} out of bounds for {} operators.",
-                    max_carrier, num_ops
-                ));
-            }
-        }
-        // Basic validation passed. More could be added (e.g., check matrix content indices).
-        Ok(Self { matrix, carriers })
+        let feedback_mode = FeedbackMode::default();
+        let schedule = Self::compile(&matrix, &carriers, feedback_mode)?;
+        Ok(Self {
+            matrix,
+            carriers,
+            feedback_mode,
+            schedule,
+        })
+    }
+
+    /// Compiles the schedule again, after `matrix` or `carriers` were changed.
+    /// `SynthEngine::set_algorithm` does this for every algorithm it is given.
+    pub fn recompile(&mut self) -> Result<(), String> {
+        self.schedule = Self::compile(&self.matrix, &self.carriers, self.feedback_mode)?;
+        Ok(())
+    }
+
+    /// Number of operators the algorithm connects
+    pub fn operator_count(&self) -> usize {
+        self.matrix.len()
+    }
+
+    /// How the feedback loops are evaluated
+    pub fn feedback_mode(&self) -> FeedbackMode {
+        self.feedback_mode
+    }
+
+    /// Switch how the feedback loops are evaluated, recompiling the schedule
+    pub fn set_feedback_mode(&mut self, mode: FeedbackMode) -> Result<(), String> {
+        self.schedule = Self::compile(&self.matrix, &self.carriers, mode)?;
+        self.feedback_mode = mode;
+        Ok(())
     }
 
     /// Default: Single carrier (operator 0), no modulation.
@@ -78,75 +196,298 @@ impl Algorithm {
         Self::new(matrix, vec![0])
     }
 
-    /// Processes the algorithm, filling the output buffer.
-    /// Builds an unrolled DAG internally and processes it recursively.
+    /// DX7 algorithm `number` (1-32), with the DX7's operators 1-6 as operators 0-5. Any
+    /// further operators are left unconnected. The feedback connection has one level (N=2),
+    /// run in `FeedbackMode::SingleSample` as on the DX7. Unrolling only keeps feedback that
+    /// goes straight into a carrier, so `FeedbackMode::Unrolled` loses it in most algorithms.
+    pub fn dx7(number: usize, num_operators: usize) -> Result<Self, String> {
+        Self::classic("DX7", &DX7_ALGORITHMS, 6, number, num_operators)
+    }
+
+    /// Four-operator algorithm `number` (1-8) of the TX81Z, DX100, DX21 and DX27, with their
+    /// operators 1-4 as operators 0-3. Operator 4 feeds back on itself with one level (N=2),
+    /// in `FeedbackMode::SingleSample`; unrolled, that only works in algorithm 8.
+    pub fn tx81z(number: usize, num_operators: usize) -> Result<Self, String> {
+        Self::classic("TX81Z", &TX81Z_ALGORITHMS, 4, number, num_operators)
+    }
+
+    /// Builds entry `number` (1-based) of a table of classic algorithms.
+    fn classic(
+        name: &str,
+        table: &[ClassicAlgorithm],
+        operators_used: usize,
+        number: usize,
+        num_operators: usize,
+    ) -> Result<Self, String> {
+        let algorithm = number
+            .checked_sub(1)
+            .and_then(|index| table.get(index))
+            .ok_or_else(|| {
+                format!(
+                    "There is no {} algorithm {}; they run from 1 to {}.",
+                    name,
+                    number,
+                    table.len()
+                )
+            })?;
+        if num_operators < operators_used {
+            return Err(format!(
+                "{} algorithms need {} operators, but there are {}.",
+                name, operators_used, num_operators
+            ));
+        }
+
+        let mut matrix = vec![vec![None; num_operators]; num_operators];
+        for &(modulator, target) in algorithm.connections {
+            matrix[target - 1][modulator - 1] = Some(1);
+        }
+        let (modulator, target) = algorithm.feedback;
+        matrix[target - 1][modulator - 1] = Some(2);
+        let carriers = algorithm.carriers.iter().map(|op| op - 1).collect();
+        let mut algorithm = Self::new(matrix, carriers)?;
+        algorithm.set_feedback_mode(FeedbackMode::SingleSample)?;
+        Ok(algorithm)
+    }
+
+    /// Processes the algorithm, filling the stereo output buffers.
+    /// Runs the compiled schedule, using `buffers` for every intermediate signal.
+    /// `states` holds the calling voice's per-operator state and is advanced by one buffer.
+    /// `modulation` holds the voice's pitch for each sample of the output, plus any
+    /// per-operator modulation. Each carrier is placed in the stereo field by its operator's `pan`,
+    /// plus any pan modulation.
+    #[allow(clippy::too_many_arguments)]
     pub fn process(
         &self,
         operators: &[Operator],
-        base_frequency: f32,
-        output: &mut [f32],
+        states: &mut [OperatorState],
+        modulation: &VoiceModulation,
+        buffers: &mut AlgorithmBuffers,
+        output_left: &mut [f32],
+        output_right: &mut [f32],
         sample_rate: f32,
-        start_sample_index: u64,
     ) {
-        let buffer_size = output.len();
-        output.fill(0.0); // Clear output initially
+        let buffer_size = output_left.len().min(output_right.len());
+        output_left.fill(0.0); // Clear output initially
+        output_right.fill(0.0);
 
         let num_operators = operators.len();
-        if buffer_size == 0 || num_operators == 0 || self.matrix.len() != num_operators {
-            if self.matrix.len() != num_operators && num_operators > 0 {
-                eprintln!("Warning: Algorithm matrix size ({}) differs from number of operators ({}). No processing.", self.matrix.len(), num_operators);
+        let compiled_operators = self.schedule.operator_count;
+        if buffer_size == 0 || num_operators == 0 || compiled_operators != num_operators {
+            if compiled_operators != num_operators && num_operators > 0 {
+                eprintln!("Warning: Algorithm matrix size ({}) differs from number of operators ({}). No processing.", compiled_operators, num_operators);
             }
             return;
         }
+        if states.len() != num_operators {
+            eprintln!(
+                "Warning: Operator state count ({}) differs from number of operators ({}). No processing.",
+                states.len(),
+                num_operators
+            );
+            return;
+        }
 
-        // 1. Build the internal unrolled graph representation.
-        match Self::build_processor(&self.matrix, &self.carriers, operators) {
-            Ok(processor) => {
-                // 2. Process the built graph.
-                let mut modulation_input_buffer: Vec<f32> = vec![0.0; buffer_size];
-
-                for &carrier_node_idx in &processor.carrier_node_indices {
-                    match processor.process_node_recursive(
-                        carrier_node_idx,
-                        base_frequency,
-                        sample_rate,
-                        start_sample_index,
-                        buffer_size,
-                        &mut modulation_input_buffer,
-                    ) {
-                        Ok(carrier_output) => {
-                            for (out_sample, carrier_sample) in
-                                output.iter_mut().zip(carrier_output.iter())
-                            {
-                                *out_sample += *carrier_sample;
-                            }
-                        }
-                        Err(e) => {
-                            eprintln!(
-                                "Error processing graph node {}: {}. Output might be incomplete.",
-                                carrier_node_idx, e
-                            );
-                        }
-                    }
+        // 1. Size the scratch buffers (no allocation once they have been big enough).
+        buffers.reserve(self, buffer_size);
+        buffers.start_states.clear();
+        buffers.start_states.extend_from_slice(states);
+
+        // 2. Run the schedule. Every step's modulators have already run.
+        for step in &self.schedule.steps {
+            match step {
+                Step::Block(step) => Self::process_block(
+                    step,
+                    operators,
+                    states,
+                    modulation,
+                    buffers,
+                    buffer_size,
+                    sample_rate,
+                ),
+                Step::Loop(members) => Self::process_loop(
+                    members,
+                    operators,
+                    states,
+                    modulation,
+                    buffers,
+                    buffer_size,
+                    sample_rate,
+                ),
+            }
+        }
+
+        // 3. Mix the carriers into the stereo output.
+        for carrier in &self.schedule.carrier_outputs {
+            let carrier_output = &buffers.slots[carrier.slot];
+            let pan = operators[carrier.operator].pan;
+            let pan_mod = &modulation.operator(carrier.operator).pan;
+            let (mut left_gain, mut right_gain) = pan_gains(pan);
+            for (i, ((left, right), carrier_sample)) in output_left
+                .iter_mut()
+                .zip(output_right.iter_mut())
+                .zip(carrier_output.iter())
+                .enumerate()
+            {
+                if let Some(offset) = pan_mod.get(i) {
+                    (left_gain, right_gain) = pan_gains((pan + offset).clamp(-1.0, 1.0));
                 }
+                *left += *carrier_sample * left_gain;
+                *right += *carrier_sample * right_gain;
             }
-            Err(e) => {
-                eprintln!("Failed to build internal processing graph: {}", e);
+        }
+    }
+
+    /// Runs one operator over the whole buffer.
+    fn process_block(
+        step: &StepOperator,
+        operators: &[Operator],
+        states: &mut [OperatorState],
+        modulation: &VoiceModulation,
+        buffers: &mut AlgorithmBuffers,
+        buffer_size: usize,
+        sample_rate: f32,
+    ) {
+        // Sum the modulators' outputs, each scaled by its modulation index
+        let input = &mut buffers.input;
+        input.clear();
+        input.resize(buffer_size, 0.0);
+        for modulator in &step.modulators {
+            let mod_output = &buffers.slots[modulator.slot];
+            let mod_strength = operators[modulator.operator].modulation_index;
+            let index_mod = &modulation.operator(modulator.operator).index;
+            if index_mod.is_empty() {
+                for (input, output) in input.iter_mut().zip(mod_output) {
+                    *input += output * mod_strength;
+                }
+            } else {
+                for ((input, output), index) in input.iter_mut().zip(mod_output).zip(index_mod) {
+                    *input += output * mod_strength * index;
+                }
+            }
+        }
+
+        // An operator can run at several feedback levels; each starts from the same
+        // snapshot, and the last one to run leaves its state for the next buffer.
+        let state = &mut states[step.operator];
+        state.clone_from(&buffers.start_states[step.operator]);
+        let output = &mut buffers.slots[step.output];
+        output.clear();
+        output.resize(buffer_size, 0.0);
+        operators[step.operator].process(
+            state,
+            modulation.base_frequency,
+            modulation.operator(step.operator),
+            output,
+            &buffers.input,
+            &mut buffers.phase_increments,
+            sample_rate,
+        );
+    }
+
+    /// Runs the operators of a feedback loop one sample at a time, in order, so each sees
+    /// this sample's output of the operators before it and the previous samples of its
+    /// feedback modulators.
+    fn process_loop(
+        members: &[StepOperator],
+        operators: &[Operator],
+        states: &mut [OperatorState],
+        modulation: &VoiceModulation,
+        buffers: &mut AlgorithmBuffers,
+        buffer_size: usize,
+        sample_rate: f32,
+    ) {
+        // Pitch and level don't depend on the loop, so they are worked out for the whole buffer
+        for (i, member) in members.iter().enumerate() {
+            operators[member.operator].prepare_samples(
+                &mut states[member.operator],
+                modulation.base_frequency,
+                modulation.operator(member.operator),
+                &mut buffers.loop_increments[i],
+                &mut buffers.loop_levels[i],
+                buffer_size,
+                sample_rate,
+            );
+            let output = &mut buffers.slots[member.output];
+            output.clear();
+            output.resize(buffer_size, 0.0);
+        }
+
+        for sample in 0..buffer_size {
+            for (i, member) in members.iter().enumerate() {
+                let mut input = 0.0;
+                for modulator in &member.modulators {
+                    let mod_strength = operators[modulator.operator].modulation_index;
+                    let index = modulation.operator(modulator.operator).index.get(sample);
+                    input += buffers.slots[modulator.slot][sample]
+                        * mod_strength
+                        * index.unwrap_or(&1.0);
+                }
+                for source in &member.feedback {
+                    // Samples before this buffer come from the modulator's history
+                    let output = &buffers.slots[source.slot];
+                    let history = states[source.operator].feedback_history;
+                    let previous = |back: usize| match sample.checked_sub(back) {
+                        Some(index) => output[index],
+                        None => history[2 + sample - back],
+                    };
+                    input +=
+                        (previous(1) + previous(2)) * 0.5 * operators[source.operator].feedback;
+                }
+                buffers.slots[member.output][sample] = operators[member.operator].process_sample(
+                    &mut states[member.operator],
+                    buffers.loop_increments[i][sample],
+                    buffers.loop_levels[i][sample],
+                    input,
+                    sample_rate,
+                );
+            }
+        }
+
+        // Keep the last two samples for the next buffer's feedback
+        for member in members {
+            let output = &buffers.slots[member.output];
+            let history = &mut states[member.operator].feedback_history;
+            for &sample in &output[output.len().saturating_sub(2)..] {
+                *history = [history[1], sample];
             }
         }
     }
 
     // --- Internal Graph Building Logic (Moved from UnrolledAlgorithmGraph) ---
 
-    /// Builds the internal processor containing the unrolled DAG.
-    fn build_processor<'a>(
+    /// Compiles the connections into a schedule, the way `mode` evaluates feedback.
+    fn compile(
         matrix: &[Vec<Option<usize>>],
         carriers: &[usize],
-        operators: &'a [Operator],
-    ) -> Result<AlgorithmProcessor<'a>, String> {
-        let num_ops = operators.len(); // Already validated in process entry
+        mode: FeedbackMode,
+    ) -> Result<Schedule, String> {
+        let num_ops = matrix.len();
+        if num_ops > 0 && !matrix.iter().all(|row| row.len() == num_ops) {
+            return Err("Adjacency matrix must be square.".to_string());
+        }
+        if let Some(max_carrier) = carriers.iter().max() {
+            if num_ops == 0 || *max_carrier >= num_ops {
+                // Also check num_ops > 0 for max_carrier check
+                return Err(format!(
+                    "Carrier index {} out of bounds for {} operators.",
+                    max_carrier, num_ops
+                ));
+            }
+        }
+        // Basic validation passed. More could be added (e.g., check matrix content indices).
+        let graph = match mode {
+            FeedbackMode::Unrolled => Self::build_graph(matrix, carriers)?,
+            FeedbackMode::SingleSample => Self::build_loop_graph(matrix, carriers)?,
+        };
+        let mut schedule = Self::schedule(&graph);
+        schedule.operator_count = num_ops;
+        Ok(schedule)
+    }
 
-        let mut final_nodes: Vec<UnrolledNode> = Vec::new();
+    /// Builds the unrolled DAG: a node for each operator at each feedback level it is
+    /// needed at, each run on its own after the nodes feeding it.
+    fn build_graph(matrix: &[Vec<Option<usize>>], carriers: &[usize]) -> Result<Graph, String> {
+        let mut final_nodes: Vec<GraphNode> = Vec::new();
         let mut created_nodes_map: HashMap<(usize, usize), usize> = HashMap::new();
 
         let max_level = matrix
@@ -169,10 +510,15 @@ impl Algorithm {
             final_carrier_indices.push(carrier_node_idx);
         }
 
-        Ok(AlgorithmProcessor {
+        let mut order = Vec::with_capacity(final_nodes.len());
+        let mut visits = vec![Visit::New; final_nodes.len()];
+        for &node_idx in &final_carrier_indices {
+            Self::visit_node(node_idx, &final_nodes, &mut visits, &mut order)?;
+        }
+        Ok(Graph {
             nodes: final_nodes,
-            operators,
-            carrier_node_indices: final_carrier_indices,
+            carrier_nodes: final_carrier_indices,
+            groups: order.into_iter().map(|node_idx| vec![node_idx]).collect(),
         })
     }
 
@@ -181,7 +527,7 @@ impl Algorithm {
         target_op_idx: usize,
         target_level: usize,
         matrix: &[Vec<Option<usize>>],
-        final_nodes: &mut Vec<UnrolledNode>,
+        final_nodes: &mut Vec<GraphNode>,
         created_nodes_map: &mut HashMap<(usize, usize), usize>,
     ) -> Result<usize, String> {
         let node_key = (target_op_idx, target_level);
@@ -190,9 +536,10 @@ impl Algorithm {
         }
 
         let current_node_idx = final_nodes.len();
-        final_nodes.push(UnrolledNode {
+        final_nodes.push(GraphNode {
             original_op_index: target_op_idx,
             input_node_indices: Vec::new(),
+            feedback_node_indices: Vec::new(),
         });
         created_nodes_map.insert(node_key, current_node_idx);
 
@@ -226,84 +573,300 @@ impl Algorithm {
         final_nodes[current_node_idx].input_node_indices = input_indices_for_current;
         Ok(current_node_idx)
     }
-}
 
-impl<'a> AlgorithmProcessor<'a> {
-    /// Recursively processes a single node in the pre-built unrolled DAG.
-    #[allow(clippy::too_many_arguments)]
-    fn process_node_recursive(
-        &self,
-        node_idx: usize, // Index in self.nodes
-        base_frequency: f32,
-        sample_rate: f32,
-        start_sample_index: u64,
-        buffer_size: usize,
-        modulation_input: &mut Vec<f32>,
-    ) -> Result<Vec<f32>, String> {
-        if node_idx >= self.nodes.len() {
-            return Err(format!("Invalid node index {}.", node_idx));
+    /// Builds the graph for single-sample feedback: one node per operator. A connection with
+    /// 2 or more passes that closes a loop becomes a one-sample feedback connection, and the
+    /// operators of each loop are grouped to run together.
+    fn build_loop_graph(
+        matrix: &[Vec<Option<usize>>],
+        carriers: &[usize],
+    ) -> Result<Graph, String> {
+        let num_ops = matrix.len();
+        let connected =
+            |target: usize, source: usize| matches!(matrix[target][source], Some(n) if n > 0);
+
+        // reaches[i][j]: operator i's output reaches operator j through some chain of connections
+        let mut reaches: Vec<Vec<bool>> = (0..num_ops)
+            .map(|source| {
+                (0..num_ops)
+                    .map(|target| connected(target, source))
+                    .collect()
+            })
+            .collect();
+        for via in 0..num_ops {
+            let via_reaches = reaches[via].clone();
+            for row in reaches.iter_mut().filter(|row| row[via]) {
+                for (reach, via_reach) in row.iter_mut().zip(&via_reaches) {
+                    *reach |= via_reach;
+                }
+            }
         }
-        let node = &self.nodes[node_idx];
-
-        modulation_input.fill(0.0);
-
-        for &input_node_idx in &node.input_node_indices {
-            match self.process_node_recursive(
-                input_node_idx,
-                base_frequency,
-                sample_rate,
-                start_sample_index,
-                buffer_size,
-                modulation_input,
-            ) {
-                Ok(mod_output) => {
-                    if input_node_idx < self.nodes.len() {
-                        let modulator_op_idx = self.nodes[input_node_idx].original_op_index;
-                        if modulator_op_idx < self.operators.len() {
-                            let mod_strength = self.operators[modulator_op_idx].modulation_index;
-                            for i in 0..buffer_size {
-                                modulation_input[i] += mod_output[i] * mod_strength;
-                            }
-                        } else {
-                            return Err(format!(
-                                "Invalid original operator index {} in node {}.",
-                                modulator_op_idx, input_node_idx
-                            ));
-                        }
-                    } else {
-                        return Err(format!(
-                            "Invalid input node index {} for node {}.",
-                            input_node_idx, node_idx
-                        ));
+        let same_loop = |a: usize, b: usize| reaches[a][b] && reaches[b][a];
+
+        let nodes: Vec<GraphNode> = (0..num_ops)
+            .map(|target| {
+                let (mut inputs, mut feedback) = (Vec::new(), Vec::new());
+                for source in (0..num_ops).filter(|&source| connected(target, source)) {
+                    match matrix[target][source] {
+                        Some(n) if n >= 2 && same_loop(source, target) => feedback.push(source),
+                        _ => inputs.push(source),
                     }
                 }
-                Err(e) => {
-                    return Err(format!(
-                        "Error processing input node {} for node {}: {}",
-                        input_node_idx, node_idx, e
-                    ));
+                GraphNode {
+                    original_op_index: target,
+                    input_node_indices: inputs,
+                    feedback_node_indices: feedback,
+                }
+            })
+            .collect();
+        // The operators sharing a loop with each operator (just itself outside a loop)
+        let loops: Vec<Vec<usize>> = (0..num_ops)
+            .map(|op| {
+                (0..num_ops)
+                    .filter(|&other| other == op || same_loop(op, other))
+                    .collect()
+            })
+            .collect();
+
+        let mut groups = Vec::new();
+        let mut visits = vec![Visit::New; num_ops];
+        for &op_idx in carriers {
+            Self::visit_loop(op_idx, &nodes, &loops, &mut visits, &mut groups)?;
+        }
+        Ok(Graph {
+            nodes,
+            carrier_nodes: carriers.to_vec(),
+            groups,
+        })
+    }
+
+    /// Adds the loop containing `node_idx` to `groups` after every loop feeding it, with
+    /// its own operators in the order they modulate each other within a sample.
+    fn visit_loop(
+        node_idx: usize,
+        nodes: &[GraphNode],
+        loops: &[Vec<usize>],
+        visits: &mut [Visit],
+        groups: &mut Vec<Vec<usize>>,
+    ) -> Result<(), String> {
+        if visits[node_idx] != Visit::New {
+            return Ok(());
+        }
+        let members = &loops[node_idx];
+        for &member in members {
+            let node = &nodes[member];
+            for &input_node_idx in node
+                .input_node_indices
+                .iter()
+                .chain(&node.feedback_node_indices)
+            {
+                if !members.contains(&input_node_idx) {
+                    Self::visit_loop(input_node_idx, nodes, loops, visits, groups)?;
                 }
             }
         }
+        // Everything outside the loop is done, so this only orders the loop's own operators
+        let mut order = Vec::with_capacity(members.len());
+        for &member in members {
+            Self::visit_node(member, nodes, visits, &mut order)?;
+        }
+        groups.push(order);
+        Ok(())
+    }
 
-        let current_op_idx = node.original_op_index;
-        if current_op_idx >= self.operators.len() {
-            return Err(format!(
-                "Invalid original operator index {} in node {}.",
-                current_op_idx, node_idx
-            ));
+    /// Adds `node_idx` to `order` after every node feeding it within the same sample (a
+    /// depth-first post-order). Fails on a loop where every connection has a single pass, as
+    /// it has no feedback level to start from.
+    fn visit_node(
+        node_idx: usize,
+        nodes: &[GraphNode],
+        visits: &mut [Visit],
+        order: &mut Vec<usize>,
+    ) -> Result<(), String> {
+        match visits[node_idx] {
+            Visit::Done => return Ok(()),
+            Visit::InProgress => {
+                return Err(format!(
+                    "Operator {} is in a modulation loop with no feedback passes; \
+                     give a connection in the loop 2 or more passes.",
+                    nodes[node_idx].original_op_index
+                ));
+            }
+            Visit::New => {}
+        }
+        visits[node_idx] = Visit::InProgress;
+        for &input_node_idx in &nodes[node_idx].input_node_indices {
+            Self::visit_node(input_node_idx, nodes, visits, order)?;
         }
+        visits[node_idx] = Visit::Done;
+        order.push(node_idx);
+        Ok(())
+    }
 
-        let mut current_op_output = vec![0.0; buffer_size];
-        self.operators[current_op_idx].process(
-            base_frequency,
-            &mut current_op_output,
-            modulation_input,
-            sample_rate,
-            start_sample_index,
-        );
+    /// Turns the graph's groups into steps, giving each node's output a buffer slot that is
+    /// handed on once nothing reads it any more.
+    fn schedule(graph: &Graph) -> Schedule {
+        let nodes = &graph.nodes;
+        let reads = |node_idx: usize| {
+            let node = &nodes[node_idx];
+            node.input_node_indices
+                .iter()
+                .chain(&node.feedback_node_indices)
+                .copied()
+        };
+
+        // The step each node's output is read for the last time. Carriers are read after the
+        // whole schedule, so their slots are never handed on.
+        let mut last_read = vec![0; nodes.len()];
+        for (step, group) in graph.groups.iter().enumerate() {
+            for input_node_idx in group.iter().flat_map(|&node_idx| reads(node_idx)) {
+                last_read[input_node_idx] = step;
+            }
+        }
+        for &node_idx in &graph.carrier_nodes {
+            last_read[node_idx] = usize::MAX;
+        }
+
+        let mut schedule = Schedule::default();
+        let mut node_slots = vec![0; nodes.len()];
+        let mut free_slots = Vec::new();
+        for (step, group) in graph.groups.iter().enumerate() {
+            let is_loop = group.len() > 1 || !nodes[group[0]].feedback_node_indices.is_empty();
+            // A loop reads its inputs on every sample, so its outputs need slots of their own
+            if is_loop {
+                for &node_idx in group {
+                    node_slots[node_idx] = take_slot(&mut free_slots, &mut schedule.slot_count);
+                }
+            }
+            let slot_ref = |node_idx: usize| SlotRef {
+                operator: nodes[node_idx].original_op_index,
+                slot: node_slots[node_idx],
+            };
+            let mut members: Vec<StepOperator> = group
+                .iter()
+                .map(|&node_idx| StepOperator {
+                    operator: nodes[node_idx].original_op_index,
+                    modulators: nodes[node_idx]
+                        .input_node_indices
+                        .iter()
+                        .map(|&i| slot_ref(i))
+                        .collect(),
+                    feedback: nodes[node_idx]
+                        .feedback_node_indices
+                        .iter()
+                        .map(|&i| slot_ref(i))
+                        .collect(),
+                    output: node_slots[node_idx],
+                })
+                .collect();
+
+            // Hand on the slots read for the last time here (each only once)
+            for node_idx in group.iter().flat_map(|&node_idx| reads(node_idx)) {
+                if last_read[node_idx] == step {
+                    last_read[node_idx] = usize::MAX;
+                    free_slots.push(node_slots[node_idx]);
+                }
+            }
+
+            if is_loop {
+                schedule.loop_size = schedule.loop_size.max(members.len());
+                schedule.steps.push(Step::Loop(members));
+            } else {
+                // The modulators are summed before the operator runs, so a slot just handed
+                // on can already take its output
+                let mut member = members.remove(0);
+                member.output = take_slot(&mut free_slots, &mut schedule.slot_count);
+                node_slots[group[0]] = member.output;
+                schedule.steps.push(Step::Block(member));
+            }
+        }
+
+        schedule.carrier_outputs = graph
+            .carrier_nodes
+            .iter()
+            .map(|&node_idx| SlotRef {
+                operator: nodes[node_idx].original_op_index,
+                slot: node_slots[node_idx],
+            })
+            .collect();
+        schedule
+    }
+}
+
+/// A slot from `free_slots`, or a new one if none is free.
+fn take_slot(free_slots: &mut Vec<usize>, slot_count: &mut usize) -> usize {
+    free_slots.pop().unwrap_or_else(|| {
+        *slot_count += 1;
+        *slot_count - 1
+    })
+}
+
+// --- Classic Algorithm Tables ---
+
+/// A Yamaha algorithm as drawn in the manuals, with operators numbered from 1.
+struct ClassicAlgorithm {
+    carriers: &'static [usize],
+    connections: &'static [(usize, usize)], // (modulator, target)
+    feedback: (usize, usize), // (modulator, target) of the connection closing the loop
+}
 
-        Ok(current_op_output)
+/// Shorthand for the tables below.
+const fn algorithm(
+    carriers: &'static [usize],
+    connections: &'static [(usize, usize)],
+    feedback: (usize, usize),
+) -> ClassicAlgorithm {
+    ClassicAlgorithm {
+        carriers,
+        connections,
+        feedback,
     }
 }
 
+/// The 32 DX7 algorithms, in order.
+const DX7_ALGORITHMS: [ClassicAlgorithm; 32] = [
+    algorithm(&[1, 3], &[(2, 1), (4, 3), (5, 4), (6, 5)], (6, 6)), // 1
+    algorithm(&[1, 3], &[(2, 1), (4, 3), (5, 4), (6, 5)], (2, 2)), // 2
+    algorithm(&[1, 4], &[(2, 1), (3, 2), (5, 4), (6, 5)], (6, 6)), // 3
+    algorithm(&[1, 4], &[(2, 1), (3, 2), (5, 4), (6, 5)], (4, 6)), // 4
+    algorithm(&[1, 3, 5], &[(2, 1), (4, 3), (6, 5)], (6, 6)),      // 5
+    algorithm(&[1, 3, 5], &[(2, 1), (4, 3), (6, 5)], (5, 6)),      // 6
+    algorithm(&[1, 3], &[(2, 1), (4, 3), (5, 3), (6, 5)], (6, 6)), // 7
+    algorithm(&[1, 3], &[(2, 1), (4, 3), (5, 3), (6, 5)], (4, 4)), // 8
+    algorithm(&[1, 3], &[(2, 1), (4, 3), (5, 3), (6, 5)], (2, 2)), // 9
+    algorithm(&[1, 4], &[(2, 1), (3, 2), (5, 4), (6, 4)], (3, 3)), // 10
+    algorithm(&[1, 4], &[(2, 1), (3, 2), (5, 4), (6, 4)], (6, 6)), // 11
+    algorithm(&[1, 3], &[(2, 1), (4, 3), (5, 3), (6, 3)], (2, 2)), // 12
+    algorithm(&[1, 3], &[(2, 1), (4, 3), (5, 3), (6, 3)], (6, 6)), // 13
+    algorithm(&[1, 3], &[(2, 1), (4, 3), (5, 4), (6, 4)], (6, 6)), // 14
+    algorithm(&[1, 3], &[(2, 1), (4, 3), (5, 4), (6, 4)], (2, 2)), // 15
+    algorithm(&[1], &[(2, 1), (3, 1), (5, 1), (4, 3), (6, 5)], (6, 6)), // 16
+    algorithm(&[1], &[(2, 1), (3, 1), (5, 1), (4, 3), (6, 5)], (2, 2)), // 17
+    algorithm(&[1], &[(2, 1), (3, 1), (4, 1), (5, 4), (6, 5)], (3, 3)), // 18
+    algorithm(&[1, 4, 5], &[(2, 1), (3, 2), (6, 4), (6, 5)], (6, 6)), // 19
+    algorithm(&[1, 2, 4], &[(3, 1), (3, 2), (5, 4), (6, 4)], (3, 3)), // 20
+    algorithm(&[1, 2, 4, 5], &[(3, 1), (3, 2), (6, 4), (6, 5)], (3, 3)), // 21
+    algorithm(&[1, 3, 4, 5], &[(2, 1), (6, 3), (6, 4), (6, 5)], (6, 6)), // 22
+    algorithm(&[1, 2, 4, 5], &[(3, 2), (6, 4), (6, 5)], (6, 6)),   // 23
+    algorithm(&[1, 2, 3, 4, 5], &[(6, 3), (6, 4), (6, 5)], (6, 6)), // 24
+    algorithm(&[1, 2, 3, 4, 5], &[(6, 4), (6, 5)], (6, 6)),        // 25
+    algorithm(&[1, 2, 4], &[(3, 2), (5, 4), (6, 4)], (6, 6)),      // 26
+    algorithm(&[1, 2, 4], &[(3, 2), (5, 4), (6, 4)], (3, 3)),      // 27
+    algorithm(&[1, 3, 6], &[(2, 1), (4, 3), (5, 4)], (5, 5)),      // 28
+    algorithm(&[1, 2, 3, 5], &[(4, 3), (6, 5)], (6, 6)),           // 29
+    algorithm(&[1, 2, 3, 6], &[(4, 3), (5, 4)], (5, 5)),           // 30
+    algorithm(&[1, 2, 3, 4, 5], &[(6, 5)], (6, 6)),                // 31
+    algorithm(&[1, 2, 3, 4, 5, 6], &[], (6, 6)),                   // 32
+];
+
+/// The 8 four-operator algorithms of the TX81Z and its relatives, in order.
+const TX81Z_ALGORITHMS: [ClassicAlgorithm; 8] = [
+    algorithm(&[1], &[(2, 1), (3, 2), (4, 3)], (4, 4)), // 1
+    algorithm(&[1], &[(2, 1), (3, 2), (4, 2)], (4, 4)), // 2
+    algorithm(&[1], &[(2, 1), (3, 2), (4, 1)], (4, 4)), // 3
+    algorithm(&[1], &[(2, 1), (3, 1), (4, 3)], (4, 4)), // 4
+    algorithm(&[1, 3], &[(2, 1), (4, 3)], (4, 4)),      // 5
+    algorithm(&[1, 2, 3], &[(4, 1), (4, 2), (4, 3)], (4, 4)), // 6
+    algorithm(&[1, 2, 3], &[(4, 3)], (4, 4)),           // 7
+    algorithm(&[1, 2, 3, 4], &[], (4, 4)),              // 8
+];
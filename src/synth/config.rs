@@ -1,8 +1,48 @@
+/// How the engine picks a voice to take over when every voice is busy.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VoiceStealingStrategy {
+    Oldest,        // The note that started longest ago
+    Quietest,      // The voice with the lowest envelope level
+    ReleasedFirst, // The oldest released note, falling back to the oldest note
+    SameNote,      // Retrigger a voice already playing the note, falling back to the oldest note
+}
+
+/// Whether each note gets its own voice or all notes share one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlayMode {
+    Poly,
+    Mono, // One voice follows the held note chosen by `NotePriority`
+}
+
+/// Which held note a mono voice plays.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NotePriority {
+    Last, // Most recently pressed
+    Low,  // Lowest held
+    High, // Highest held
+}
+
 #[derive(Clone)]
 pub struct SynthConfig {
     pub max_voices: usize,
     pub operators_per_voice: usize,
     pub sample_rate: f32,
+    pub noise_seed: u64, // Base seed for every voice's noise generators
+    pub voice_stealing: VoiceStealingStrategy,
+    pub protect_lowest_note: bool, // Never steal the lowest held note (e.g. a bass line)
+    pub protect_highest_note: bool, // Never steal the highest held note (e.g. a melody)
+    pub steal_fade_ms: f32,        // Fade-out applied to a stolen voice before it restarts
+    pub play_mode: PlayMode,
+    pub note_priority: NotePriority, // Mono mode only
+    pub legato: bool, // Mono mode: changing notes while one is held doesn't retrigger envelopes
+    pub unison_voices: usize, // Voices stacked on every note
+    pub unison_detune: f32, // Detune of the outermost unison voices, in cents
+    pub unison_spread: f32, // Stereo width of the unison stack (0.0 = centre, 1.0 = full)
+    pub tempo: f32,   // Beats per minute, used by tempo-synced LFOs
+    pub mod_matrix_slots: usize, // Number of slots in the mod matrix
+    pub pitch_bend_range: f32, // Semitones a full pitch bend moves either way
+    pub event_queue_size: usize, // Events and commands that can wait for the audio thread
+    pub max_block_size: usize, // Longest stretch rendered in one pass; sizes the scratch buffers
 }
 
 impl Default for SynthConfig {
@@ -11,6 +51,22 @@ impl Default for SynthConfig {
             max_voices: 128,
             operators_per_voice: 12,
             sample_rate: 44100.0, // Standard audio sample rate
+            noise_seed: 0,
+            voice_stealing: VoiceStealingStrategy::ReleasedFirst,
+            protect_lowest_note: false,
+            protect_highest_note: false,
+            steal_fade_ms: 5.0,
+            play_mode: PlayMode::Poly,
+            note_priority: NotePriority::Last,
+            legato: false,
+            unison_voices: 1,
+            unison_detune: 12.0,
+            unison_spread: 0.5,
+            tempo: 120.0,
+            mod_matrix_slots: 16,
+            pitch_bend_range: 2.0,
+            event_queue_size: 1024,
+            max_block_size: 512,
         }
     }
 }
@@ -1,25 +1,54 @@
-use super::algorithm::Algorithm;
-use super::config::SynthConfig;
-use super::note::NoteEvent;
+use super::algorithm::{Algorithm, FeedbackMode};
+use super::config::{NotePriority, PlayMode, SynthConfig, VoiceStealingStrategy};
+use super::filter::{FilterSettings, LadderSettings};
+use super::glide::Portamento;
+use super::lfo::{Lfo, LfoSettings};
+use super::modulation::{ModDestination, ModMatrix, ModSlot, ModSourceValues, SharedModulation};
+use super::mono::{HeldNote, NoteStack};
+use super::noise::derive_seed;
+use super::note::{
+    ChannelEvent, ControlEvent, NoteEvent, NoteSource, TimedEvent, CC_MOD_WHEEL, CC_SOSTENUTO,
+    CC_SUSTAIN,
+};
 use super::operator::Operator;
 use super::operator::OperatorEvent;
-use super::voice::Voice;
+use super::sequencer::Sequencer;
+use super::voice::{UnisonOffset, VelocityCurve, Voice, VoiceParams};
 use super::waveform::Waveform;
+use crate::utils::smoothing::Smoother;
+use std::f32::consts::FRAC_1_SQRT_2;
 use std::sync::mpsc::{Receiver, Sender};
 
 /// The main synthesizer engine that manages voices and audio processing
 pub struct SynthEngine {
     pub voices: Vec<Voice>,
     pub config: SynthConfig,
-    note_receiver: Receiver<NoteEvent>,
-    note_sender: Sender<NoteEvent>,
+    event_receiver: Receiver<TimedEvent>,
+    event_sender: Sender<TimedEvent>,
     operator_receiver: Receiver<OperatorEvent>,
     operator_sender: Sender<OperatorEvent>,
     master_volume: f32,
     current_gain: f32, // Track the current gain for smooth transitions
     buffer_size: usize,
-    algorithm: Algorithm,     // The algorithm defining operator connections
-    operators: Vec<Operator>, // The set of operators shared by all voices
+    algorithm: Algorithm,         // The algorithm defining operator connections
+    operators: Vec<Operator>,     // The set of operators shared by all voices
+    voice_params: VoiceParams,    // Voice-level settings shared by all voices
+    next_note_id: u64,            // Id for the next note, used to tell older voices from newer
+    held_notes: NoteStack,        // Keys held in mono mode
+    sustain_pedal: bool,          // CC64 is down
+    sostenuto_pedal: bool,        // CC66 is down
+    sequencer: Option<Sequencer>, // MIDI file playing along with the audio
+    global_lfos: Vec<Lfo>,        // State for the global LFOs in VoiceParams::lfos
+    shared_modulation: SharedModulation, // Per-buffer signals shared by all voices
+    pitch_bend: Smoother,         // Bend in semitones, eased towards the wheel position
+    pending_events: Vec<TimedEvent>, // Received events not yet due, sorted by time
+    sample_clock: u64,            // Samples rendered so far; the time base of TimedEvent
+    // Scratch buffers, sized up front so processing doesn't allocate
+    mix: [Vec<f32>; 2],          // Sum of the voices before the gain
+    voice_output: [Vec<f32>; 2], // One voice's output while it is measured and mixed in
+    interleave: [Vec<f32>; 2],   // Stereo output of process_interleaved before interleaving
+    mod_amounts: Vec<f32>,       // One mod slot's output, for the master volume
+    voice_indices: Vec<usize>,   // Voices picked for a new note
 }
 
 impl SynthEngine {
@@ -27,9 +56,15 @@ impl SynthEngine {
         Self::default()
     }
 
-    /// Get a sender for note events that can be used by input handlers
-    pub fn get_note_sender(&self) -> Sender<NoteEvent> {
-        self.note_sender.clone()
+    /// Get a sender for note and controller events that can be used by input handlers.
+    /// Events sent without a time apply at the start of the next buffer.
+    pub fn get_event_sender(&self) -> Sender<TimedEvent> {
+        self.event_sender.clone()
+    }
+
+    /// Number of samples rendered so far: the clock `TimedEvent::time` is measured on
+    pub fn sample_clock(&self) -> u64 {
+        self.sample_clock
     }
 
     /// Get a sender for operator events that can be used by input handlers
@@ -37,16 +72,111 @@ impl SynthEngine {
         self.operator_sender.clone()
     }
 
-    /// Find an available voice (one that is completely finished)
-    fn find_free_voice(&mut self) -> Option<&mut Voice> {
-        self.voices.iter_mut().find(|voice| voice.is_finished())
+    /// Pick `count` voices for a new note: the voices already playing it when retriggering same
+    /// notes, then free voices, then whole unison groups stolen according to the configured
+    /// strategy. Voices of a stolen group that the note doesn't need are faded out.
+    fn allocate_voices(
+        &mut self,
+        chosen: &mut Vec<usize>,
+        note_number: u8,
+        source: NoteSource,
+        count: usize,
+        fade_samples: usize,
+    ) {
+        chosen.clear();
+        if self.config.voice_stealing == VoiceStealingStrategy::SameNote {
+            chosen.extend(
+                (0..self.voices.len())
+                    .filter(|&i| self.voices[i].plays_note(note_number, Some(source)))
+                    .take(count),
+            );
+        }
+        for i in 0..self.voices.len() {
+            if chosen.len() >= count {
+                break;
+            }
+            if self.voices[i].is_finished() && !chosen.contains(&i) {
+                chosen.push(i);
+            }
+        }
+
+        while chosen.len() < count {
+            let Some(stolen) = self.steal_voice(chosen) else {
+                break; // More unison voices than the engine has
+            };
+            // A unison group is stolen as a whole
+            let note_id = self.voices[stolen].note_id;
+            for i in 0..self.voices.len() {
+                let voice = &self.voices[i];
+                let in_group = i == stolen
+                    || (voice.note_id == note_id && !voice.is_finished() && !chosen.contains(&i));
+                if !in_group {
+                    continue;
+                }
+                if chosen.len() < count {
+                    chosen.push(i);
+                } else {
+                    self.voices[i].fade_out(fade_samples);
+                }
+            }
+        }
     }
 
-    // TODO: Implement a better voice stealing strategy (e.g., oldest note, quietest voice)
-    fn steal_voice(&mut self) -> &mut Voice {
-        // Simple strategy: steal the first voice. Replace with a better heuristic.
-        eprintln!("Warning: Stealing voice 0"); // Log voice stealing
-        &mut self.voices[0]
+    /// Choose a busy voice to take over, following `config.voice_stealing`.
+    /// Voices in `exclude` are skipped; returns None if no voice is left.
+    fn steal_voice(&self, exclude: &[usize]) -> Option<usize> {
+        // Only keys that are down count as held; notes kept by a pedal can be taken
+        let held_notes = || {
+            self.voices
+                .iter()
+                .filter(|voice| voice.active && !voice.sustained)
+                .map(|voice| voice.note_number)
+        };
+        let lowest = held_notes()
+            .min()
+            .filter(|_| self.config.protect_lowest_note);
+        let highest = held_notes()
+            .max()
+            .filter(|_| self.config.protect_highest_note);
+
+        // Protected notes are skipped unless nothing else is left
+        let available = || (0..self.voices.len()).filter(|i| !exclude.contains(i));
+        let protected = |i: usize| {
+            let voice = &self.voices[i];
+            let note = Some(voice.note_number);
+            voice.active && !voice.sustained && (note == lowest || note == highest)
+        };
+        let skip_protected = available().any(|i| !protected(i));
+        let candidates = || available().filter(move |&i| !(skip_protected && protected(i)));
+
+        // The oldest candidate passing `filter`
+        let oldest = |filter: fn(&Voice) -> bool| {
+            candidates()
+                .filter(|&i| filter(&self.voices[i]))
+                .min_by_key(|&i| self.voices[i].note_id)
+        };
+        match self.config.voice_stealing {
+            VoiceStealingStrategy::Oldest | VoiceStealingStrategy::SameNote => oldest(|_| true),
+            VoiceStealingStrategy::Quietest => candidates()
+                .min_by(|&a, &b| self.voices[a].level().total_cmp(&self.voices[b].level())),
+            VoiceStealingStrategy::ReleasedFirst => oldest(Voice::is_releasing)
+                // Then notes whose keys are up but a pedal is holding
+                .or_else(|| oldest(|voice| voice.sustained))
+                .or_else(|| oldest(|_| true)),
+        }
+    }
+
+    /// Detune and pan of voice `position` in a stack of `count` unison voices.
+    /// Voices are spread evenly from the lowest/leftmost to the highest/rightmost.
+    fn unison_offset(&self, position: usize, count: usize) -> UnisonOffset {
+        if count < 2 {
+            return UnisonOffset::default();
+        }
+        let spread = 2.0 * position as f32 / (count - 1) as f32 - 1.0;
+        UnisonOffset {
+            detune: spread * self.config.unison_detune,
+            pan: spread * self.config.unison_spread,
+        }
     }
 
     /// Set the master volume level (0.0 to 1.0)
@@ -54,72 +184,418 @@ impl SynthEngine {
         self.master_volume = volume.clamp(0.0, 1.0);
     }
 
+    /// The algorithm connecting the operators
+    pub fn algorithm(&self) -> &Algorithm {
+        &self.algorithm
+    }
+
+    /// Replace the algorithm. It must have one matrix row per operator.
+    pub fn set_algorithm(&mut self, mut algorithm: Algorithm) -> Result<(), String> {
+        algorithm.recompile()?;
+        if algorithm.operator_count() != self.operators.len() {
+            return Err(format!(
+                "Algorithm has {} operators but the engine has {}.",
+                algorithm.operator_count(),
+                self.operators.len()
+            ));
+        }
+        self.algorithm = algorithm;
+        self.prepare_voices();
+        Ok(())
+    }
+
+    /// Choose how the algorithm's feedback loops are evaluated. The modes can be switched
+    /// freely to compare them.
+    pub fn set_feedback_mode(&mut self, mode: FeedbackMode) -> Result<(), String> {
+        self.algorithm.set_feedback_mode(mode)?;
+        self.prepare_voices();
+        Ok(())
+    }
+
+    /// The operators shared by all voices
+    pub fn operators(&self) -> &[Operator] {
+        &self.operators
+    }
+
+    /// Mutable access to the shared operators, e.g. to change envelopes or filters
+    pub fn operators_mut(&mut self) -> &mut [Operator] {
+        &mut self.operators
+    }
+
+    /// Set (or with None, remove) the filter applied to each voice's output
+    pub fn set_voice_filter(&mut self, filter: Option<FilterSettings>) {
+        self.voice_params.filter = filter;
+    }
+
+    /// Set (or with None, remove) the resonant ladder filter and its envelope on each voice
+    pub fn set_ladder_filter(&mut self, ladder: Option<LadderSettings>) {
+        self.voice_params.ladder = ladder;
+    }
+
+    /// Set how note velocity maps to level, and how strongly it affects the voice's volume.
+    /// Operators scale their own output through `Operator::velocity_sensitivity`.
+    pub fn set_velocity_response(&mut self, curve: VelocityCurve, sensitivity: f32) {
+        self.voice_params.velocity_curve = curve;
+        self.voice_params.velocity_sensitivity = sensitivity.clamp(0.0, 1.0);
+    }
+
+    /// Switch between polyphonic and monophonic play. Sounding notes are released.
+    pub fn set_play_mode(&mut self, mode: PlayMode) {
+        self.config.play_mode = mode;
+        self.held_notes.clear();
+        for voice in self.voices.iter_mut() {
+            // Released now, so lifting a pedal later mustn't release them again
+            voice.sustained = false;
+            voice.sostenuto = false;
+            voice.release();
+        }
+    }
+
+    /// Choose which held note plays in mono mode
+    pub fn set_note_priority(&mut self, priority: NotePriority) {
+        self.config.note_priority = priority;
+    }
+
+    /// In mono mode, whether moving between held notes keeps the envelopes running
+    pub fn set_legato(&mut self, legato: bool) {
+        self.config.legato = legato;
+    }
+
+    /// Set (or with None, remove) the glide between notes in mono mode
+    pub fn set_portamento(&mut self, portamento: Option<Portamento>) {
+        self.voice_params.portamento = portamento;
+    }
+
+    /// Stack `voices` voices on every note, detuned up to `detune` cents either side of the
+    /// note and panned across `spread` (0.0 = centre, 1.0 = full width). Applies to new notes.
+    pub fn set_unison(&mut self, voices: usize, detune: f32, spread: f32) {
+        self.config.unison_voices = voices.clamp(1, self.voices.len().max(1));
+        self.config.unison_detune = detune.max(0.0);
+        self.config.unison_spread = spread.clamp(0.0, 1.0);
+    }
+
+    /// Replace the LFOs. Per-voice LFOs run in every voice; the others are shared.
+    pub fn set_lfos(&mut self, lfos: Vec<LfoSettings>) {
+        self.voice_params.lfos = lfos;
+        self.prepare_voices();
+    }
+
+    /// Fill (or with None, clear) one slot of the mod matrix
+    pub fn set_mod_slot(&mut self, index: usize, slot: Option<ModSlot>) -> Result<(), String> {
+        self.voice_params.mod_matrix.set_slot(index, slot)
+    }
+
+    /// The mod matrix shared by all voices
+    pub fn mod_matrix(&self) -> &ModMatrix {
+        &self.voice_params.mod_matrix
+    }
+
+    /// Set the mod wheel position (0.0 to 1.0), read as a mod matrix source
+    pub fn set_mod_wheel(&mut self, value: f32) {
+        self.shared_modulation.mod_wheel = value.clamp(0.0, 1.0);
+    }
+
+    /// Set the channel aftertouch (0.0 to 1.0), read as a mod matrix source
+    pub fn set_aftertouch(&mut self, value: f32) {
+        self.shared_modulation.aftertouch = value.clamp(0.0, 1.0);
+    }
+
+    /// Set the pitch bend wheel position (-1.0 to 1.0). Sounding voices glide to the new
+    /// pitch over a few milliseconds; the position is also a mod matrix source.
+    pub fn set_pitch_bend(&mut self, value: f32) {
+        self.shared_modulation.pitch_bend = value.clamp(-1.0, 1.0);
+    }
+
+    /// Set how far a full pitch bend moves the pitch, in semitones either way
+    pub fn set_pitch_bend_range(&mut self, semitones: f32) {
+        self.config.pitch_bend_range = semitones.clamp(0.0, 48.0);
+    }
+
+    /// Press or lift the sustain pedal. While it is down, released keys keep sounding.
+    pub fn set_sustain_pedal(&mut self, down: bool) {
+        self.sustain_pedal = down;
+        if !down {
+            self.release_pedal_notes();
+        }
+    }
+
+    /// Press or lift the sostenuto pedal. Pressing it catches the keys held at that moment,
+    /// which then keep sounding after they are released until the pedal comes up.
+    pub fn set_sostenuto_pedal(&mut self, down: bool) {
+        if down == self.sostenuto_pedal {
+            return;
+        }
+        self.sostenuto_pedal = down;
+        for voice in self.voices.iter_mut() {
+            voice.sostenuto = down && voice.is_key_down();
+        }
+        for note in self.held_notes.iter_mut() {
+            note.sostenuto = down && !note.sustained;
+        }
+        if !down {
+            self.release_pedal_notes();
+        }
+    }
+
+    /// Release the notes whose keys are up and that no pedal holds any more
+    fn release_pedal_notes(&mut self) {
+        let sustain = self.sustain_pedal;
+        for voice in self.voices.iter_mut() {
+            if voice.sustained && !sustain && !voice.sostenuto {
+                voice.sustained = false;
+                voice.release();
+            }
+        }
+
+        let previous = self.held_notes.current(self.config.note_priority);
+        self.held_notes
+            .retain(|note| !note.sustained || sustain || note.sostenuto);
+        if self.config.play_mode == PlayMode::Mono && previous.is_some() {
+            self.update_mono_voices(previous);
+        }
+    }
+
+    /// Play a MIDI file along with the audio (or with None, stop). Notes still sounding from
+    /// the previous sequencer are released.
+    pub fn set_sequencer(&mut self, sequencer: Option<Sequencer>) {
+        if let Some(previous) = &mut self.sequencer {
+            let sender = &self.event_sender;
+            previous.all_notes_off(|event| {
+                let _ = sender.send(event.into());
+            });
+        }
+        self.sequencer = sequencer;
+    }
+
+    /// The playing sequencer, e.g. to set loop points or seek
+    pub fn sequencer_mut(&mut self) -> Option<&mut Sequencer> {
+        self.sequencer.as_mut()
+    }
+
+    /// Set the tempo in beats per minute, for tempo-synced LFOs
+    pub fn set_tempo(&mut self, tempo: f32) {
+        self.config.tempo = tempo.max(1.0);
+    }
+
+    /// Reseed every voice's noise generators. The same seed gives bit-identical noise
+    /// for the same sequence of notes, which keeps offline renders repeatable.
+    pub fn set_noise_seed(&mut self, seed: u64) {
+        self.config.noise_seed = seed;
+        for (i, voice) in self.voices.iter_mut().enumerate() {
+            voice.seed_noise(derive_seed(seed, i as u64));
+        }
+    }
+
     /// Process operator events
     fn process_operator_events(&mut self) {
         while let Ok(event) = self.operator_receiver.try_recv() {
-            match event {
-                OperatorEvent::CycleWaveform { direction } => {
-                    println!("Processing CycleWaveform event: {:?}", direction);
-                    // Cycle the waveform for *all* operators managed by the engine
-                    for (i, operator) in self.operators.iter_mut().enumerate() {
-                        operator.cycle_waveform(direction);
-                        // Log the waveform of the first operator as an example
-                        println!(
-                            "Operator {:?} waveform changed to: {:?}",
-                            i, operator.waveform_generator
-                        );
-                    }
-                } // Add other OperatorEvent cases here
-            }
+            self.apply_operator_event(event);
+        }
+    }
+
+    /// Apply one operator event
+    pub fn apply_operator_event(&mut self, event: OperatorEvent) {
+        match event {
+            OperatorEvent::CycleWaveform { direction } => {
+                // Cycle the waveform for *all* operators managed by the engine
+                for operator in self.operators.iter_mut() {
+                    operator.cycle_waveform(direction);
+                }
+            } // Add other OperatorEvent cases here
+        }
+    }
+
+    /// Queue a note or controller event directly, as the event sender would. These events
+    /// wait together with those from the event sender and the sequencer; the engine only
+    /// allocates if more than `config.event_queue_size` of them are waiting at once.
+    pub fn queue_event(&mut self, event: TimedEvent) {
+        insert_event(&mut self.pending_events, event);
+    }
+
+    /// Size the scratch buffers of the voices and the shared modulation for the current
+    /// algorithm and LFOs, so rendering up to `config.max_block_size` samples doesn't allocate
+    fn prepare_voices(&mut self) {
+        let block = self.config.max_block_size;
+        for voice in self.voices.iter_mut() {
+            voice.prepare(&self.algorithm, &self.operators, &self.voice_params, block);
+        }
+
+        let lfo_count = self.voice_params.lfos.len();
+        self.global_lfos
+            .reserve(lfo_count.saturating_sub(self.global_lfos.len()));
+        let shared = &mut self.shared_modulation;
+        shared.lfos.resize_with(lfo_count, Vec::new);
+        for buffer in shared.lfos.iter_mut().chain([&mut shared.bend]) {
+            buffer.reserve(block.saturating_sub(buffer.len()));
         }
     }
 
-    /// Process audio for the current buffer
+    /// Process audio for the current buffer as mono (both stereo channels mixed down)
     pub fn process(&mut self, output: &mut [f32], sample_rate: f32) {
-        // Handle any pending note events
-        self.process_note_events();
+        self.process_interleaved(output, 1, sample_rate);
+    }
+
+    /// Process audio into an interleaved buffer with `channels` channels per frame.
+    /// Mono gets a mixdown; with more than two channels the rest are left silent.
+    pub fn process_interleaved(&mut self, output: &mut [f32], channels: usize, sample_rate: f32) {
+        let channels = channels.max(1);
+        let block = self.config.max_block_size.max(1);
+        let [mut left, mut right] = std::mem::take(&mut self.interleave);
+        for output in output.chunks_mut(block * channels) {
+            let frames = output.len() / channels;
+            left.resize(frames, 0.0);
+            right.resize(frames, 0.0);
+            self.process_stereo(&mut left, &mut right, sample_rate);
+
+            for ((frame, left), right) in output.chunks_mut(channels).zip(&left).zip(&right) {
+                if channels == 1 {
+                    // Undo the -3 dB centre pan so a centred voice keeps its level in mono
+                    frame[0] = (left + right) * FRAC_1_SQRT_2;
+                } else {
+                    frame[0] = *left;
+                    frame[1] = *right;
+                    frame[2..].fill(0.0);
+                }
+            }
+        }
+        self.interleave = [left, right];
+    }
+
+    /// Process audio for the current buffer into separate left and right buffers. The buffer
+    /// is rendered in pieces split at each event's sample, so notes start exactly on time,
+    /// and no longer than `config.max_block_size`.
+    pub fn process_stereo(&mut self, left: &mut [f32], right: &mut [f32], sample_rate: f32) {
+        let buffer_size = left.len().min(right.len());
+        self.collect_events(buffer_size, sample_rate);
 
         // Handle any pending operator events
         self.process_operator_events();
 
-        // Clear output buffer
-        output.fill(0.0); // Clear the main output buffer first
+        let block = self.config.max_block_size.max(1);
+        let mut start = 0;
+        while start < buffer_size {
+            // Apply the events due by this sample, then render up to the next one
+            let now = self.sample_clock + start as u64;
+            self.process_events(now, sample_rate);
+            let end = self
+                .pending_events
+                .first()
+                .map_or(buffer_size, |event| {
+                    (event.time - self.sample_clock) as usize
+                })
+                .min(buffer_size)
+                .min(start + block);
+            self.render(&mut left[start..end], &mut right[start..end], sample_rate);
+            start = end;
+        }
+        self.sample_clock += buffer_size as u64;
+    }
+
+    /// Render a stretch of audio with no events in it
+    fn render(&mut self, left: &mut [f32], right: &mut [f32], sample_rate: f32) {
+        let buffer_size = left.len();
 
-        // Process voices, generate their audio into temporary buffers, and calculate energy
-        let (total_energy, voice_buffers) = self.process_voices(output.len(), sample_rate);
+        // Clear output buffers
+        left.fill(0.0); // Clear the main output buffers first
+        right.fill(0.0);
+
+        // Run the global LFOs once for every voice to share
+        self.update_shared_modulation(buffer_size, sample_rate);
+
+        // Process voices, mix their audio into the mix buffers, and calculate energy
+        let total_energy = self.process_voices(buffer_size, sample_rate);
 
         // Calculate target gain based on the combined energy of active voices
         let target_gain = self.calculate_target_gain(total_energy);
 
         // Mix voices and apply gain with anti-pop processing
-        self.mix_voices_with_gain(output, voice_buffers, target_gain, sample_rate);
+        self.mix_voices_with_gain(left, right, target_gain, sample_rate);
+
+        // Apply any mod matrix slots targeting the master volume
+        self.apply_master_volume_mod(left, right);
 
         // Apply soft knee limiter for safety
-        self.apply_limiter(output);
+        self.apply_limiter(left, right);
     }
 
-    /// Process any pending note events from the queue
-    fn process_note_events(&mut self) {
-        while let Ok(event) = self.note_receiver.try_recv() {
-            if event.is_on {
-                // Find a free voice or steal one
-                let voice = if let Some(v) = self.find_free_voice() {
-                    v
-                } else {
-                    self.steal_voice()
-                };
-
-                // Activate the voice with the note details
-                voice.activate(event.note_number, Some(event.source), event.frequency);
-            } else {
-                // Find all voices playing this note from the same source and release them
-                for voice in self.voices.iter_mut() {
-                    // Check if the voice is active OR still releasing (envelope not finished)
-                    // and matches the note number and source.
-                    if (!voice.is_finished() || voice.active) // Check if it's making sound or just triggered
-                        && voice.note_number == event.note_number
-                        && voice.note_source == Some(event.source)
-                    {
+    /// Gather the events sent since the last buffer and the sequencer's events for this one
+    /// into `pending_events`, sorted by time. Events with the same time keep the order they
+    /// were sent in, with the sequencer's after the rest.
+    fn collect_events(&mut self, buffer_size: usize, sample_rate: f32) {
+        let pending = &mut self.pending_events;
+        for event in self.event_receiver.try_iter() {
+            insert_event(pending, event);
+        }
+        if let Some(sequencer) = &mut self.sequencer {
+            let clock = self.sample_clock;
+            sequencer.advance(buffer_size, sample_rate, |offset, event| {
+                insert_event(pending, TimedEvent::at(clock + offset as u64, event));
+            });
+        }
+    }
+
+    /// Apply every pending note and controller event due at or before sample `now`, in order
+    fn process_events(&mut self, now: u64, sample_rate: f32) {
+        let fade_samples = (self.config.steal_fade_ms.max(0.0) / 1000.0 * sample_rate) as usize;
+        let due = self
+            .pending_events
+            .partition_point(|event| event.time <= now);
+        for index in 0..due {
+            match self.pending_events[index].event {
+                ChannelEvent::Note(event) => self.process_note_event(&event, fade_samples),
+                ChannelEvent::Control(event) => self.process_control_event(&event),
+            }
+        }
+        self.pending_events.drain(..due);
+    }
+
+    /// Start or release voices for one note event
+    fn process_note_event(&mut self, event: &NoteEvent, fade_samples: usize) {
+        // The first note of a phrase restarts the global LFOs' delay (and with key sync, cycle)
+        if event.is_on && !self.voices.iter().any(|voice| voice.active) {
+            self.trigger_global_lfos();
+        }
+        if self.config.play_mode == PlayMode::Mono {
+            self.process_mono_note_event(event);
+        } else if event.is_on {
+            // Find free voices or steal some, one per unison voice
+            let count = self.config.unison_voices.max(1);
+            let mut indices = std::mem::take(&mut self.voice_indices);
+            self.allocate_voices(
+                &mut indices,
+                event.note_number,
+                event.source,
+                count,
+                fade_samples,
+            );
+            // The whole unison stack shares one id, so it ages and is stolen as a unit
+            let note_id = self.next_note_id;
+            self.next_note_id += 1;
+
+            for (position, &index) in indices.iter().enumerate() {
+                let unison = self.unison_offset(position, count);
+                let voice = &mut self.voices[index];
+                voice.note_id = note_id;
+                // Start the note, fading out whatever the voice was still playing
+                voice.steal(
+                    event.note_number,
+                    Some(event.source),
+                    event.frequency,
+                    event.velocity,
+                    unison,
+                    fade_samples,
+                );
+            }
+            self.voice_indices = indices;
+        } else {
+            // Find all voices playing this note from the same source and release them
+            let sustain = self.sustain_pedal;
+            for voice in self.voices.iter_mut() {
+                // Check if the voice is sounding (or about to sound) this note from this source
+                if voice.plays_note(event.note_number, Some(event.source)) {
+                    if sustain || voice.sostenuto {
+                        voice.sustained = true; // Released when the pedal comes up
+                    } else {
                         voice.release(); // Initiate the release phase
                     }
                 }
@@ -127,33 +603,168 @@ impl SynthEngine {
         }
     }
 
-    /// Process all voices that are not finished, return their total energy and individual buffers.
-    fn process_voices(&mut self, buffer_size: usize, sample_rate: f32) -> (f32, Vec<Vec<f32>>) {
+    /// Apply a controller event. Controllers the engine doesn't use are ignored.
+    fn process_control_event(&mut self, event: &ControlEvent) {
+        match *event {
+            ControlEvent::PitchBend(value) => self.set_pitch_bend(value),
+            ControlEvent::ControlChange { controller, value } => {
+                let value = value.min(127);
+                match controller {
+                    CC_MOD_WHEEL => self.set_mod_wheel(value as f32 / 127.0),
+                    CC_SUSTAIN => self.set_sustain_pedal(value >= 64),
+                    CC_SOSTENUTO => self.set_sostenuto_pedal(value >= 64),
+                    _ => {}
+                }
+            }
+            ControlEvent::Tempo(tempo) => self.set_tempo(tempo),
+            ControlEvent::ChannelPressure(value) => {
+                self.set_aftertouch(value.min(127) as f32 / 127.0)
+            }
+        }
+    }
+
+    fn trigger_global_lfos(&mut self) {
+        for (lfo, settings) in self.global_lfos.iter_mut().zip(&self.voice_params.lfos) {
+            lfo.trigger(settings);
+        }
+    }
+
+    /// Run the global LFOs for this buffer into `shared_modulation`
+    fn update_shared_modulation(&mut self, buffer_size: usize, sample_rate: f32) {
+        let lfos = &self.voice_params.lfos;
+        let previous_len = self.global_lfos.len();
+        self.global_lfos.resize_with(lfos.len(), Lfo::default);
+        for (i, lfo) in self.global_lfos.iter_mut().enumerate().skip(previous_len) {
+            *lfo = Lfo::new(derive_seed(
+                self.config.noise_seed,
+                GLOBAL_LFO_SEED_STREAM + i as u64,
+            ));
+        }
+
+        let shared = &mut self.shared_modulation;
+        shared.tempo = self.config.tempo;
+        shared.lfos.resize_with(lfos.len(), Vec::new);
+        for ((lfo, settings), output) in self.global_lfos.iter_mut().zip(lfos).zip(&mut shared.lfos)
+        {
+            output.clear();
+            if !settings.per_voice {
+                output.resize(buffer_size, 0.0);
+                lfo.process(settings, output, shared.tempo, sample_rate);
+            }
+        }
+
+        // Pitch bend as a frequency ratio per sample, eased so the pitch never steps
+        self.pitch_bend
+            .set_target(shared.pitch_bend * self.config.pitch_bend_range);
+        shared.bend.clear();
+        if !(self.pitch_bend.is_settled() && self.pitch_bend.value() == 0.0) {
+            shared.bend.resize(buffer_size, 0.0);
+            self.pitch_bend.process(&mut shared.bend, sample_rate);
+            for value in shared.bend.iter_mut() {
+                *value = (*value / 12.0).exp2();
+            }
+        }
+    }
+
+    /// Mono mode: the first voices (one per unison voice) play whichever held note the note
+    /// priority picks, moving (with legato) or retriggering when that note changes.
+    fn process_mono_note_event(&mut self, event: &NoteEvent) {
+        let priority = self.config.note_priority;
+        let previous = self.held_notes.current(priority);
+        if event.is_on {
+            self.held_notes.push(HeldNote {
+                note_number: event.note_number,
+                source: event.source,
+                frequency: event.frequency,
+                velocity: event.velocity,
+                sustained: false,
+                sostenuto: false,
+            });
+        } else {
+            let sustain = self.sustain_pedal;
+            match self.held_notes.get_mut(event.note_number, event.source) {
+                // A pedal keeps the note in the stack until it comes up
+                Some(note) if sustain || note.sostenuto => note.sustained = true,
+                _ => self.held_notes.remove(event.note_number, event.source),
+            }
+        }
+        self.update_mono_voices(previous);
+    }
+
+    /// Mono mode: move, retrigger or release the mono voices if the note the priority picks
+    /// from the stack is no longer `previous`.
+    fn update_mono_voices(&mut self, previous: Option<HeldNote>) {
+        let next = self.held_notes.current(self.config.note_priority);
+        let key = |note: Option<HeldNote>| note.map(|note| (note.note_number, note.source));
+        if next.is_some() && key(previous) == key(next) {
+            return; // The sounding note didn't change
+        }
+        let count = self.config.unison_voices.max(1).min(self.voices.len());
+        let note_id = self.next_note_id;
+        for position in 0..count {
+            let unison = self.unison_offset(position, count);
+            let voice = &mut self.voices[position];
+            match next {
+                None => voice.release(),
+                Some(note) if self.config.legato && previous.is_some() && voice.active => {
+                    voice.legato_to(note.note_number, note.frequency);
+                }
+                Some(note) => {
+                    voice.note_id = note_id;
+                    voice.retrigger(
+                        note.note_number,
+                        Some(note.source),
+                        note.frequency,
+                        note.velocity,
+                    );
+                    voice.unison = unison;
+                }
+            }
+        }
+        self.next_note_id += 1;
+    }
+
+    /// Process all voices that are not finished, mixing them into `mix`. Returns their total
+    /// energy.
+    fn process_voices(&mut self, buffer_size: usize, sample_rate: f32) -> f32 {
         let mut total_energy = 0.0;
-        // Pre-allocate buffers for voices that will be processed
-        let active_voice_count = self.voices.iter().filter(|v| !v.is_finished()).count();
-        let mut voice_buffers = Vec::with_capacity(active_voice_count);
+        let [mix_left, mix_right] = &mut self.mix;
+        mix_left.clear();
+        mix_left.resize(buffer_size, 0.0);
+        mix_right.clear();
+        mix_right.resize(buffer_size, 0.0);
 
         // Process only voices that are not fully finished (active or releasing)
+        let [left, right] = &mut self.voice_output;
         for voice in self.voices.iter_mut().filter(|v| !v.is_finished()) {
-            let mut voice_buffer = vec![0.0; buffer_size];
+            left.clear();
+            left.resize(buffer_size, 0.0);
+            right.clear();
+            right.resize(buffer_size, 0.0);
 
             // Process the voice using the engine's algorithm and operators
             voice.process(
                 &self.algorithm,
                 &self.operators,
-                &mut voice_buffer,
+                &self.voice_params,
+                &self.shared_modulation,
+                left,
+                right,
                 sample_rate,
             );
 
-            // Calculate voice energy (RMS power) after processing
-            let voice_energy = voice_buffer.iter().map(|s| s * s).sum::<f32>() / buffer_size as f32;
+            // Calculate voice energy (RMS power) after processing, summed over both channels
+            let voice_energy =
+                left.iter().chain(right.iter()).map(|s| s * s).sum::<f32>() / buffer_size as f32;
 
             total_energy += voice_energy;
-            voice_buffers.push(voice_buffer); // Add the processed buffer
+            for i in 0..buffer_size {
+                mix_left[i] += left[i];
+                mix_right[i] += right[i];
+            }
         }
 
-        (total_energy, voice_buffers) // Return total energy and the buffers of processed voices
+        total_energy
     }
 
     /// Calculate the target gain based on total energy and master volume
@@ -168,23 +779,16 @@ impl SynthEngine {
         energy_gain * self.master_volume
     }
 
-    /// Mix all voice buffers with gain and apply crossfade to prevent pops
+    /// Copy the voice mix to the output with gain and apply crossfade to prevent pops
     fn mix_voices_with_gain(
         &mut self,
-        output: &mut [f32],
-        voice_buffers: Vec<Vec<f32>>,
+        left: &mut [f32],
+        right: &mut [f32],
         target_gain: f32,
         sample_rate: f32,
     ) {
-        // Create a temporary buffer for mixing
-        let mut temp_buffer = vec![0.0; output.len()];
-
-        // Mix all voice buffers into the temporary buffer
-        for voice_buffer in voice_buffers {
-            for (i, sample) in voice_buffer.iter().enumerate() {
-                temp_buffer[i] += *sample;
-            }
-        }
+        let buffer_size = left.len();
+        let [temp_left, temp_right] = &self.mix;
 
         // Calculate crossfade parameters
         let gain_ratio = if self.current_gain > 0.0 {
@@ -200,7 +804,7 @@ impl SynthEngine {
         let crossfade_ms =
             base_crossfade_ms + gain_change_factor * (max_crossfade_ms - base_crossfade_ms);
         let crossfade_samples = (crossfade_ms / 1000.0 * sample_rate) as usize;
-        let crossfade_samples = crossfade_samples.min(output.len());
+        let crossfade_samples = crossfade_samples.min(buffer_size);
 
         // Apply crossfade at the beginning of the buffer
         for i in 0..crossfade_samples {
@@ -208,25 +812,51 @@ impl SynthEngine {
             let t = i as f32 / crossfade_samples as f32;
             let smooth_t = t * t * (3.0 - 2.0 * t); // Cubic easing function
             let fade_in_gain = self.current_gain * (1.0 - smooth_t) + target_gain * smooth_t;
-            output[i] = temp_buffer[i] * fade_in_gain;
+            left[i] = temp_left[i] * fade_in_gain;
+            right[i] = temp_right[i] * fade_in_gain;
         }
 
         // Apply target gain to the rest of the buffer
-        for i in crossfade_samples..output.len() {
-            output[i] = temp_buffer[i] * target_gain;
+        for i in crossfade_samples..buffer_size {
+            left[i] = temp_left[i] * target_gain;
+            right[i] = temp_right[i] * target_gain;
         }
 
         // Update current gain
         self.current_gain = target_gain;
     }
 
-    /// Apply a soft knee limiter to prevent clipping
-    fn apply_limiter(&self, output: &mut [f32]) {
-        for sample in output.iter_mut() {
-            if sample.abs() > 0.9 {
-                let excess = (sample.abs() - 0.9) / 0.1;
+    /// Scale the mix by the mod matrix slots routed to the master volume. Only sources shared
+    /// by every voice (LFOs, controllers) reach it; per-note sources read as zero.
+    fn apply_master_volume_mod(&mut self, left: &mut [f32], right: &mut [f32]) {
+        let sources = ModSourceValues::global(&self.shared_modulation);
+        let amounts = &mut self.mod_amounts;
+        amounts.clear();
+        amounts.resize(left.len().min(right.len()), 0.0);
+        for slot in self.voice_params.mod_matrix.active_slots() {
+            if slot.destination != ModDestination::MasterVolume {
+                continue;
+            }
+            sources.fill(slot.source, amounts);
+            for ((left, right), amount) in left.iter_mut().zip(right.iter_mut()).zip(amounts.iter())
+            {
+                let gain = (1.0 + slot.curve.apply(*amount) * slot.amount).max(0.0);
+                *left *= gain;
+                *right *= gain;
+            }
+        }
+    }
+
+    /// Apply a soft knee limiter to prevent clipping. Both channels share the gain reduction,
+    /// driven by the louder one, so limiting doesn't shift the stereo image.
+    fn apply_limiter(&self, left: &mut [f32], right: &mut [f32]) {
+        for (left, right) in left.iter_mut().zip(right.iter_mut()) {
+            let peak = left.abs().max(right.abs());
+            if peak > 0.9 {
+                let excess = (peak - 0.9) / 0.1;
                 let scale = 1.0 - excess * 0.1;
-                *sample *= scale;
+                *left *= scale;
+                *right *= scale;
             }
         }
     }
@@ -238,10 +868,24 @@ impl SynthEngine {
         self.buffer_size = buffer_size;
     }
 }
+/// Add `event` to `pending` after every event due at or before it, keeping the list in
+/// time order without sorting it again
+fn insert_event(pending: &mut Vec<TimedEvent>, event: TimedEvent) {
+    let index = pending.partition_point(|queued| queued.time <= event.time);
+    pending.insert(index, event);
+}
+
+/// Noise stream offset for global LFO seeds, keeping them apart from the voices' streams.
+const GLOBAL_LFO_SEED_STREAM: u64 = 1 << 32;
+/// Time constant of the pitch bend smoothing, in seconds.
+const PITCH_BEND_SMOOTHING: f32 = 0.005;
+/// Mono mode notes that can be held before the note stack has to grow.
+const MAX_HELD_NOTES: usize = 128;
+
 impl Default for SynthEngine {
     fn default() -> Self {
         let config = SynthConfig::default();
-        let (note_tx, note_rx) = std::sync::mpsc::channel();
+        let (event_tx, event_rx) = std::sync::mpsc::channel();
         let (op_tx, op_rx) = std::sync::mpsc::channel();
 
         // Initialize operators
@@ -258,14 +902,30 @@ impl Default for SynthEngine {
         // let default_algorithm = Algorithm::default_stack_2(config.operators_per_voice);
         // Or use a single carrier with feedback:
         let default_algorithm = Algorithm::default_feedback_1(operators.len()).unwrap();
-        // Initialize voices using the parameterless constructor
-        let voices = (0..config.max_voices).map(|_| Voice::new()).collect();
+        // Initialize voices using the parameterless constructor, each with its own noise stream
+        let voices = (0..config.max_voices)
+            .map(|i| {
+                let mut voice = Voice::new();
+                voice.seed_noise(derive_seed(config.noise_seed, i as u64));
+                voice
+            })
+            .collect();
+
+        let voice_params = VoiceParams {
+            mod_matrix: ModMatrix::new(config.mod_matrix_slots),
+            ..VoiceParams::default()
+        };
+        let pending_events = Vec::with_capacity(config.event_queue_size);
+        let block = config.max_block_size;
+        let scratch = || [Vec::with_capacity(block), Vec::with_capacity(block)];
+        let voice_indices = Vec::with_capacity(config.max_voices);
+        let held_notes = NoteStack::with_capacity(MAX_HELD_NOTES);
 
-        Self {
+        let mut engine = Self {
             voices,
             config,
-            note_receiver: note_rx,
-            note_sender: note_tx,
+            event_receiver: event_rx,
+            event_sender: event_tx,
             operator_receiver: op_rx,
             operator_sender: op_tx,
             master_volume: 0.65,
@@ -273,6 +933,24 @@ impl Default for SynthEngine {
             buffer_size: 1024, // Default, can be updated by set_buffer_size
             algorithm: default_algorithm,
             operators, // Store the operators
-        }
+            voice_params,
+            next_note_id: 0,
+            held_notes,
+            sustain_pedal: false,
+            sostenuto_pedal: false,
+            sequencer: None,
+            global_lfos: Vec::new(),
+            shared_modulation: SharedModulation::default(),
+            pitch_bend: Smoother::new(0.0, PITCH_BEND_SMOOTHING),
+            pending_events,
+            sample_clock: 0,
+            mix: scratch(),
+            voice_output: scratch(),
+            interleave: scratch(),
+            mod_amounts: Vec::with_capacity(block),
+            voice_indices,
+        };
+        engine.prepare_voices();
+        engine
     }
 }
@@ -1,3 +1,4 @@
+#[derive(Clone, Debug)]
 pub struct EnvelopeGenerator {
     pub attack: f32,
     pub decay: f32,
@@ -32,6 +33,25 @@ impl EnvelopeGenerator {
         }
     }
 
+    /// Creates an envelope with the given ADSR settings (times in seconds, sustain as a level).
+    pub fn with_adsr(attack: f32, decay: f32, sustain: f32, release: f32) -> Self {
+        Self {
+            attack,
+            decay,
+            sustain,
+            release,
+            ..Self::new()
+        }
+    }
+
+    /// Copies the ADSR settings from another envelope, leaving the running state untouched.
+    pub fn copy_settings_from(&mut self, other: &EnvelopeGenerator) {
+        self.attack = other.attack;
+        self.decay = other.decay;
+        self.sustain = other.sustain;
+        self.release = other.release;
+    }
+
     pub fn trigger(&mut self) {
         // println!(
         //     "Envelope trigger: state={:?}, value={}",
@@ -59,6 +79,12 @@ impl EnvelopeGenerator {
         }
     }
 
+    /// Stops the envelope at once, returning it to idle at zero.
+    pub fn reset(&mut self) {
+        self.state = EnvelopeState::Idle;
+        self.value = 0.0;
+    }
+
     pub fn is_finished(&self) -> bool {
         self.state == EnvelopeState::Idle && self.value == 0.0
     }
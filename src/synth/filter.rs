@@ -1,51 +1,355 @@
-#[derive(Clone, Debug)]
+use super::envelope::EnvelopeGenerator;
+use std::f32::consts::PI;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum FilterType {
-    LowPass(f32),       // cutoff frequency
-    HighPass(f32),      // cutoff frequency
-    BandPass(f32, f32), // center frequency, bandwidth
+    LowPass,
+    HighPass,
+    BandPass, // Unity gain at the centre frequency
+    Notch,
+    Peak, // Bell boost/cut by `gain_db`
+    LowShelf,
+    HighShelf,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilterTopology {
+    Biquad,        // RBJ cookbook biquad, transposed direct form II
+    StateVariable, // Trapezoidal (TPT) state-variable filter; stays stable under fast cutoff changes
 }
 
-pub fn apply_filter(output: &mut [f32], filter_type: FilterType, sample_rate: f32) {
-    match filter_type {
-        FilterType::LowPass(cutoff) => apply_low_pass(output, cutoff, sample_rate),
-        FilterType::HighPass(cutoff) => apply_high_pass(output, cutoff, sample_rate),
-        FilterType::BandPass(center, bandwidth) => {
-            apply_band_pass(output, center, bandwidth, sample_rate)
+/// Filter settings shared by every voice. The running state lives in `Filter`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FilterSettings {
+    pub filter_type: FilterType,
+    pub topology: FilterTopology,
+    pub cutoff: f32,    // Cutoff or centre frequency in Hz
+    pub resonance: f32, // Q; 0.707 is a flat (Butterworth) response
+    pub gain_db: f32,   // Used by Peak, LowShelf and HighShelf
+}
+
+impl FilterSettings {
+    pub fn new(
+        filter_type: FilterType,
+        topology: FilterTopology,
+        cutoff: f32,
+        resonance: f32,
+    ) -> Self {
+        Self {
+            filter_type,
+            topology,
+            cutoff,
+            resonance,
+            gain_db: 0.0,
         }
     }
 }
 
-// Basic low-pass filter using a simple averaging technique
-fn apply_low_pass(output: &mut [f32], cutoff: f32, sample_rate: f32) {
-    let rc = 1.0 / (cutoff * 2.0 * std::f32::consts::PI);
-    let dt = 1.0 / sample_rate;
-    let alpha = dt / (rc + dt);
+impl Default for FilterSettings {
+    fn default() -> Self {
+        Self::new(
+            FilterType::LowPass,
+            FilterTopology::StateVariable,
+            20000.0,
+            0.707,
+        )
+    }
+}
+
+/// Per-voice filter state. Keeps its memory between buffers, so processing a stream in
+/// chunks gives the same result as processing it in one go.
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    coefficients: Coefficients,
+    // Settings and sample rate the coefficients were computed for
+    computed_for: Option<(FilterSettings, f32)>,
+    s1: f32, // Biquad: z^-1 state; SVF: first integrator
+    s2: f32, // Biquad: z^-2 state; SVF: second integrator
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+enum Coefficients {
+    #[default]
+    Bypass,
+    Biquad {
+        b0: f32,
+        b1: f32,
+        b2: f32,
+        a1: f32,
+        a2: f32,
+    },
+    StateVariable {
+        a1: f32,
+        a2: f32,
+        a3: f32,
+        m0: f32,
+        m1: f32,
+        m2: f32,
+    },
+}
+
+impl Filter {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-    let mut previous = output[0];
-    for sample in output.iter_mut() {
-        *sample = previous + alpha * (*sample - previous);
-        previous = *sample;
+    /// Clears the filter memory, e.g. when a voice starts a new note.
+    pub fn reset(&mut self) {
+        self.s1 = 0.0;
+        self.s2 = 0.0;
+    }
+
+    /// Filters `buffer` in place, recomputing coefficients only when the settings change.
+    pub fn process(&mut self, settings: &FilterSettings, buffer: &mut [f32], sample_rate: f32) {
+        if self.computed_for != Some((*settings, sample_rate)) {
+            let topology_changed = self
+                .computed_for
+                .is_some_and(|(previous, _)| previous.topology != settings.topology);
+            if topology_changed {
+                // The two topologies keep different kinds of state
+                self.reset();
+            }
+            self.coefficients = Coefficients::compute(settings, sample_rate);
+            self.computed_for = Some((*settings, sample_rate));
+        }
+
+        match self.coefficients {
+            Coefficients::Bypass => {}
+            Coefficients::Biquad { b0, b1, b2, a1, a2 } => {
+                for sample in buffer.iter_mut() {
+                    let input = *sample;
+                    let output = b0 * input + self.s1;
+                    self.s1 = b1 * input - a1 * output + self.s2;
+                    self.s2 = b2 * input - a2 * output;
+                    *sample = output;
+                }
+            }
+            Coefficients::StateVariable {
+                a1,
+                a2,
+                a3,
+                m0,
+                m1,
+                m2,
+            } => {
+                for sample in buffer.iter_mut() {
+                    let v0 = *sample;
+                    let v3 = v0 - self.s2;
+                    let v1 = a1 * self.s1 + a2 * v3;
+                    let v2 = self.s2 + a2 * self.s1 + a3 * v3;
+                    self.s1 = 2.0 * v1 - self.s1;
+                    self.s2 = 2.0 * v2 - self.s2;
+                    *sample = m0 * v0 + m1 * v1 + m2 * v2;
+                }
+            }
+        }
     }
 }
 
-// Basic high-pass filter using a simple high-pass formula
-fn apply_high_pass(output: &mut [f32], cutoff: f32, sample_rate: f32) {
-    let rc = 1.0 / (cutoff * 2.0 * std::f32::consts::PI);
-    let dt = 1.0 / sample_rate;
-    let alpha = rc / (rc + dt);
+impl Coefficients {
+    fn compute(settings: &FilterSettings, sample_rate: f32) -> Self {
+        if sample_rate <= 0.0 {
+            return Coefficients::Bypass;
+        }
+        // Keep the cutoff safely inside (0, Nyquist)
+        let cutoff = settings.cutoff.clamp(10.0, sample_rate * 0.49);
+        let q = settings.resonance.max(0.05);
+        match settings.topology {
+            FilterTopology::Biquad => Self::biquad(settings, cutoff, q, sample_rate),
+            FilterTopology::StateVariable => Self::state_variable(settings, cutoff, q, sample_rate),
+        }
+    }
+
+    /// RBJ "Audio EQ Cookbook" coefficients, normalized by a0.
+    fn biquad(settings: &FilterSettings, cutoff: f32, q: f32, sample_rate: f32) -> Self {
+        let w0 = 2.0 * PI * cutoff / sample_rate;
+        let (sin_w0, cos_w0) = w0.sin_cos();
+        let alpha = sin_w0 / (2.0 * q);
+        let a = 10.0f32.powf(settings.gain_db / 40.0);
+        let sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
 
-    let mut previous_input = output[0];
-    let mut previous_output = output[0];
-    for sample in output.iter_mut() {
-        let current_input = *sample;
-        *sample = alpha * (previous_output + current_input - previous_input);
-        previous_input = current_input;
-        previous_output = *sample;
+        let (b0, b1, b2, a0, a1, a2) = match settings.filter_type {
+            FilterType::LowPass => (
+                (1.0 - cos_w0) / 2.0,
+                1.0 - cos_w0,
+                (1.0 - cos_w0) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos_w0,
+                1.0 - alpha,
+            ),
+            FilterType::HighPass => (
+                (1.0 + cos_w0) / 2.0,
+                -(1.0 + cos_w0),
+                (1.0 + cos_w0) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos_w0,
+                1.0 - alpha,
+            ),
+            FilterType::BandPass => (alpha, 0.0, -alpha, 1.0 + alpha, -2.0 * cos_w0, 1.0 - alpha),
+            FilterType::Notch => (
+                1.0,
+                -2.0 * cos_w0,
+                1.0,
+                1.0 + alpha,
+                -2.0 * cos_w0,
+                1.0 - alpha,
+            ),
+            FilterType::Peak => (
+                1.0 + alpha * a,
+                -2.0 * cos_w0,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos_w0,
+                1.0 - alpha / a,
+            ),
+            FilterType::LowShelf => (
+                a * ((a + 1.0) - (a - 1.0) * cos_w0 + sqrt_a_alpha),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos_w0),
+                a * ((a + 1.0) - (a - 1.0) * cos_w0 - sqrt_a_alpha),
+                (a + 1.0) + (a - 1.0) * cos_w0 + sqrt_a_alpha,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos_w0),
+                (a + 1.0) + (a - 1.0) * cos_w0 - sqrt_a_alpha,
+            ),
+            FilterType::HighShelf => (
+                a * ((a + 1.0) + (a - 1.0) * cos_w0 + sqrt_a_alpha),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos_w0),
+                a * ((a + 1.0) + (a - 1.0) * cos_w0 - sqrt_a_alpha),
+                (a + 1.0) - (a - 1.0) * cos_w0 + sqrt_a_alpha,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos_w0),
+                (a + 1.0) - (a - 1.0) * cos_w0 - sqrt_a_alpha,
+            ),
+        };
+
+        Coefficients::Biquad {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+
+    /// Zavalishin/Simper trapezoidal SVF; each response is a mix of the input,
+    /// band-pass and low-pass outputs.
+    fn state_variable(settings: &FilterSettings, cutoff: f32, q: f32, sample_rate: f32) -> Self {
+        let a = 10.0f32.powf(settings.gain_db / 40.0);
+        let g = (PI * cutoff / sample_rate).tan();
+        let k = 1.0 / q;
+
+        let (g, k, m0, m1, m2) = match settings.filter_type {
+            FilterType::LowPass => (g, k, 0.0, 0.0, 1.0),
+            FilterType::HighPass => (g, k, 1.0, -k, -1.0),
+            FilterType::BandPass => (g, k, 0.0, k, 0.0),
+            FilterType::Notch => (g, k, 1.0, -k, 0.0),
+            FilterType::Peak => {
+                let k = 1.0 / (q * a);
+                (g, k, 1.0, k * (a * a - 1.0), 0.0)
+            }
+            FilterType::LowShelf => (g / a.sqrt(), k, 1.0, k * (a - 1.0), a * a - 1.0),
+            FilterType::HighShelf => (g * a.sqrt(), k, a * a, k * (1.0 - a) * a, 1.0 - a * a),
+        };
+
+        let a1 = 1.0 / (1.0 + g * (g + k));
+        let a2 = g * a1;
+        let a3 = g * a2;
+        Coefficients::StateVariable {
+            a1,
+            a2,
+            a3,
+            m0,
+            m1,
+            m2,
+        }
+    }
+}
+
+/// Settings for the Moog-style 4-pole ladder low-pass that follows the algorithm in each voice.
+/// The cutoff is modulated per sample by its own envelope, the played key and the velocity.
+#[derive(Clone, Debug)]
+pub struct LadderSettings {
+    pub cutoff: f32,                 // Base cutoff in Hz
+    pub resonance: f32,              // 0.0 to 1.0; self-oscillates near 1.0
+    pub drive: f32,                  // Input gain into the saturating stages
+    pub envelope: EnvelopeGenerator, // ADSR settings for the filter envelope
+    pub envelope_amount: f32,        // Cutoff shift in octaves at full envelope (may be negative)
+    pub key_tracking: f32,           // 0.0 = fixed cutoff, 1.0 = cutoff follows pitch from middle C
+    pub velocity_amount: f32,        // Cutoff shift in octaves at full velocity
+}
+
+impl Default for LadderSettings {
+    fn default() -> Self {
+        Self {
+            cutoff: 1000.0,
+            resonance: 0.3,
+            drive: 1.0,
+            envelope: EnvelopeGenerator::with_adsr(0.005, 0.3, 0.3, 0.3),
+            envelope_amount: 3.0,
+            key_tracking: 0.5,
+            velocity_amount: 1.0,
+        }
+    }
+}
+
+impl LadderSettings {
+    /// Cutoff in Hz for a given envelope level (0-1), note frequency and velocity (0-1).
+    pub fn modulated_cutoff(&self, envelope: f32, note_frequency: f32, velocity: f32) -> f32 {
+        const MIDDLE_C: f32 = 261.626;
+        let key_octaves = if note_frequency > 0.0 {
+            (note_frequency / MIDDLE_C).log2() * self.key_tracking
+        } else {
+            0.0
+        };
+        let octaves =
+            envelope * self.envelope_amount + key_octaves + velocity * self.velocity_amount;
+        self.cutoff * octaves.exp2()
+    }
+}
+
+/// Four one-pole stages with tanh saturation and global resonance feedback.
+#[derive(Clone, Debug, Default)]
+pub struct LadderFilter {
+    stages: [f32; 4],
+}
+
+impl LadderFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn reset(&mut self) {
+        self.stages = [0.0; 4];
+    }
+
+    /// Filters `buffer` in place with a per-sample `cutoff` in Hz.
+    pub fn process(
+        &mut self,
+        buffer: &mut [f32],
+        cutoff: &[f32],
+        resonance: f32,
+        drive: f32,
+        sample_rate: f32,
+    ) {
+        let feedback = 4.0 * resonance.clamp(0.0, 1.0);
+        for (sample, &cutoff) in buffer.iter_mut().zip(cutoff) {
+            let cutoff = cutoff.clamp(10.0, sample_rate * 0.45);
+            let g = 1.0 - (-2.0 * PI * cutoff / sample_rate).exp();
+
+            let input = *sample * drive;
+            // Subtracting half the input from the feedback keeps the passband level up
+            // as resonance rises
+            let x = fast_tanh(input - feedback * (self.stages[3] - 0.5 * input));
+            let [s0, s1, s2, s3] = &mut self.stages;
+            *s0 += g * (x - fast_tanh(*s0));
+            *s1 += g * (fast_tanh(*s0) - fast_tanh(*s1));
+            *s2 += g * (fast_tanh(*s1) - fast_tanh(*s2));
+            *s3 += g * (fast_tanh(*s2) - fast_tanh(*s3));
+            *sample = *s3;
+        }
     }
 }
 
-// Basic band-pass filter by combining low-pass and high-pass
-fn apply_band_pass(output: &mut [f32], center: f32, bandwidth: f32, sample_rate: f32) {
-    apply_low_pass(output, center + bandwidth / 2.0, sample_rate);
-    apply_high_pass(output, center - bandwidth / 2.0, sample_rate);
+/// Rational tanh approximation, exact at 0 and clamped to ±1 beyond ±3.
+fn fast_tanh(x: f32) -> f32 {
+    let x = x.clamp(-3.0, 3.0);
+    x * (27.0 + x * x) / (27.0 + 9.0 * x * x)
 }
@@ -0,0 +1,96 @@
+/// How portamento moves between notes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GlideMode {
+    ConstantTime, // Every glide takes `Portamento::time`, however far apart the notes are
+    ConstantRate, // Glides move one octave per `Portamento::time`, so wide intervals take longer
+}
+
+/// Portamento settings, shared by every voice through `VoiceParams`.
+#[derive(Clone, Copy, Debug)]
+pub struct Portamento {
+    pub mode: GlideMode,
+    pub time: f32, // Seconds per glide (ConstantTime) or per octave (ConstantRate)
+}
+
+impl Portamento {
+    pub fn new(mode: GlideMode, time: f32) -> Self {
+        Self {
+            mode,
+            time: time.max(0.0),
+        }
+    }
+}
+
+impl Default for Portamento {
+    fn default() -> Self {
+        Self::new(GlideMode::ConstantTime, 0.1)
+    }
+}
+
+/// Per-voice glide state. Pitch moves linearly in octaves so glides sound even across the range.
+#[derive(Clone, Debug)]
+pub struct Glide {
+    current: f32,  // Pitch being played, in octaves (log2 of the frequency)
+    target: f32,   // Pitch being glided to, in octaves
+    distance: f32, // Octaves covered by the current glide, for constant-time glides
+}
+
+impl Glide {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Moves straight to `frequency` with no glide.
+    pub fn jump_to(&mut self, frequency: f32) {
+        self.target = to_octaves(frequency);
+        self.current = self.target;
+        self.distance = 0.0;
+    }
+
+    /// Starts gliding from the current pitch to `frequency`.
+    pub fn glide_to(&mut self, frequency: f32) {
+        self.target = to_octaves(frequency);
+        self.distance = (self.target - self.current).abs();
+    }
+
+    /// Fills `output` with the frequency for each sample, advancing the glide.
+    /// Without portamento (or with a zero time) the pitch jumps to the target.
+    pub fn process(
+        &mut self,
+        portamento: Option<&Portamento>,
+        output: &mut [f32],
+        sample_rate: f32,
+    ) {
+        let step = match portamento {
+            Some(portamento) if portamento.time > 0.0 => match portamento.mode {
+                GlideMode::ConstantTime => self.distance / (portamento.time * sample_rate),
+                GlideMode::ConstantRate => 1.0 / (portamento.time * sample_rate),
+            },
+            _ => f32::INFINITY,
+        };
+
+        for sample in output.iter_mut() {
+            if self.current < self.target {
+                self.current = (self.current + step).min(self.target);
+            } else if self.current > self.target {
+                self.current = (self.current - step).max(self.target);
+            }
+            *sample = self.current.exp2();
+        }
+    }
+}
+
+impl Default for Glide {
+    fn default() -> Self {
+        Self {
+            current: 0.0,
+            target: 0.0,
+            distance: 0.0,
+        }
+    }
+}
+
+fn to_octaves(frequency: f32) -> f32 {
+    // Zero would give -inf, which never arrives anywhere
+    frequency.max(1e-3).log2()
+}
@@ -0,0 +1,148 @@
+use super::noise::NoiseGenerator;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LfoShape {
+    Sine,
+    Triangle,
+    Sawtooth, // Rising ramp
+    Square,
+    SampleAndHold, // New random level once per cycle
+}
+
+/// LFO speed, either free or locked to the engine tempo.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LfoRate {
+    Hz(f32),
+    Beats(f32), // One cycle lasts this many beats (quarter notes), e.g. 0.25 for sixteenths
+}
+
+impl LfoRate {
+    pub fn frequency(&self, tempo: f32) -> f32 {
+        match *self {
+            LfoRate::Hz(hz) => hz,
+            LfoRate::Beats(beats) if beats > 0.0 => tempo / 60.0 / beats,
+            LfoRate::Beats(_) => 0.0,
+        }
+    }
+}
+
+/// LFO settings. The depths give the fixed DX7-style routings; each operator scales them by
+/// its own pitch/amplitude/index sensitivities.
+#[derive(Clone, Debug)]
+pub struct LfoSettings {
+    pub shape: LfoShape,
+    pub rate: LfoRate,
+    pub delay: f32,       // Seconds after the trigger before the LFO starts
+    pub fade_in: f32,     // Seconds for the LFO to reach full depth after the delay
+    pub key_sync: bool,   // Restart the cycle on note-on; otherwise it runs freely
+    pub per_voice: bool,  // Every voice runs its own copy; otherwise one LFO is shared
+    pub pitch_depth: f32, // Vibrato depth in cents
+    pub amp_depth: f32,   // Tremolo depth (0.0-1.0): how far the level dips at the bottom
+    pub index_depth: f32, // Modulation index swing as a fraction of the index (0.0-1.0)
+}
+
+impl Default for LfoSettings {
+    fn default() -> Self {
+        Self {
+            shape: LfoShape::Sine,
+            rate: LfoRate::Hz(5.0),
+            delay: 0.0,
+            fade_in: 0.0,
+            key_sync: true,
+            per_voice: true,
+            pitch_depth: 0.0,
+            amp_depth: 0.0,
+            index_depth: 0.0,
+        }
+    }
+}
+
+/// Running state of one LFO.
+#[derive(Clone, Debug)]
+pub struct Lfo {
+    phase: f32,   // Position in the cycle, [0, 1)
+    held: f32,    // Current sample-and-hold level
+    elapsed: f32, // Seconds since the last trigger, for the delay and fade-in
+    noise: NoiseGenerator,
+}
+
+impl Lfo {
+    pub fn new(seed: u64) -> Self {
+        let mut noise = NoiseGenerator::new(seed);
+        Self {
+            phase: 0.0,
+            held: noise.next_white(),
+            elapsed: 0.0,
+            noise,
+        }
+    }
+
+    /// Restarts the delay and fade-in, and with key sync the cycle too.
+    pub fn trigger(&mut self, settings: &LfoSettings) {
+        self.elapsed = 0.0;
+        if settings.key_sync {
+            self.phase = 0.0;
+            self.held = self.noise.next_white();
+        }
+    }
+
+    /// Fills `output` with the LFO level (-1.0 to 1.0), scaled by the delay/fade-in ramp.
+    pub fn process(
+        &mut self,
+        settings: &LfoSettings,
+        output: &mut [f32],
+        tempo: f32,
+        sample_rate: f32,
+    ) {
+        let phase_increment = settings.rate.frequency(tempo) / sample_rate;
+        let time_step = 1.0 / sample_rate;
+        for sample in output.iter_mut() {
+            let value = match settings.shape {
+                LfoShape::Sine => (self.phase * std::f32::consts::TAU).sin(),
+                LfoShape::Triangle => {
+                    if self.phase < 0.25 {
+                        4.0 * self.phase
+                    } else if self.phase < 0.75 {
+                        2.0 - 4.0 * self.phase
+                    } else {
+                        4.0 * self.phase - 4.0
+                    }
+                }
+                LfoShape::Sawtooth => 2.0 * self.phase - 1.0,
+                LfoShape::Square => {
+                    if self.phase < 0.5 {
+                        1.0
+                    } else {
+                        -1.0
+                    }
+                }
+                LfoShape::SampleAndHold => self.held,
+            };
+            *sample = value * self.fade_level(settings);
+
+            self.elapsed += time_step;
+            self.phase += phase_increment;
+            if self.phase >= 1.0 {
+                self.phase -= self.phase.floor();
+                self.held = self.noise.next_white();
+            }
+        }
+    }
+
+    fn fade_level(&self, settings: &LfoSettings) -> f32 {
+        let time = self.elapsed - settings.delay;
+        if time < 0.0 {
+            0.0
+        } else if settings.fade_in > 0.0 {
+            (time / settings.fade_in).min(1.0)
+        } else {
+            1.0
+        }
+    }
+}
+
+impl Default for Lfo {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
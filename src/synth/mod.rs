@@ -3,8 +3,18 @@ pub mod config;
 pub mod engine;
 pub mod envelope;
 pub mod filter;
+pub mod glide;
+pub mod lfo;
+pub mod modulation;
+pub mod mono;
+pub mod noise;
 pub mod note;
 pub mod operator;
+pub mod patch;
+pub mod pan;
+pub mod realtime;
+pub mod sequencer;
 pub mod voice;
 pub mod waveform;
+pub mod wavetable;
 
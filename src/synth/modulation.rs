@@ -0,0 +1,211 @@
+/// Per-sample modulation of one operator over one buffer, filled by the voice.
+/// An empty buffer means that parameter is unmodulated.
+#[derive(Clone, Debug, Default)]
+pub struct OperatorModulation {
+    pub frequency: Vec<f32>, // Frequency multiplier
+    pub gain: Vec<f32>,      // Output level multiplier
+    pub index: Vec<f32>,     // Multiplier on the modulation index when modulating other operators
+    pub pan: Vec<f32>,       // Offset added to the pan when the operator is a carrier
+}
+
+/// Shared stand-in for operators without modulation.
+static NO_MODULATION: OperatorModulation = OperatorModulation {
+    frequency: Vec::new(),
+    gain: Vec::new(),
+    index: Vec::new(),
+    pan: Vec::new(),
+};
+
+/// Per-sample inputs an algorithm needs to drive one voice's operators for one buffer.
+pub struct VoiceModulation<'a> {
+    pub base_frequency: &'a [f32],           // Voice pitch in Hz
+    pub operators: &'a [OperatorModulation], // Indexed like the operators; missing = unmodulated
+}
+
+impl VoiceModulation<'_> {
+    pub fn operator(&self, index: usize) -> &OperatorModulation {
+        self.operators.get(index).unwrap_or(&NO_MODULATION)
+    }
+}
+
+/// Signals computed once per buffer by the engine and shared by every voice.
+#[derive(Clone, Debug)]
+pub struct SharedModulation {
+    pub tempo: f32,          // Beats per minute, for tempo-synced LFOs
+    pub lfos: Vec<Vec<f32>>, // Output of each global LFO, indexed like `VoiceParams::lfos`
+    pub mod_wheel: f32,      // 0.0-1.0
+    pub aftertouch: f32,     // Channel pressure, 0.0-1.0
+    pub pitch_bend: f32,     // -1.0 to 1.0
+    pub bend: Vec<f32>,      // Frequency ratio from the pitch bend per sample; empty when centred
+}
+
+impl Default for SharedModulation {
+    fn default() -> Self {
+        Self {
+            tempo: 120.0,
+            lfos: Vec::new(),
+            mod_wheel: 0.0,
+            aftertouch: 0.0,
+            pitch_bend: 0.0,
+            bend: Vec::new(),
+        }
+    }
+}
+
+/// Where a mod matrix slot takes its signal from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ModSource {
+    Velocity,       // Curved note velocity, 0.0-1.0
+    Key,            // Note number, 0.0 (note 0) to 1.0 (note 127)
+    Envelope,       // The voice's amplitude envelope, 0.0-1.0
+    FilterEnvelope, // The ladder filter envelope, 0.0-1.0 (0.0 without a ladder)
+    Lfo(usize),     // An LFO from `VoiceParams::lfos`, -1.0 to 1.0
+    ModWheel,       // 0.0-1.0
+    Aftertouch,     // 0.0-1.0
+    PitchBend,      // -1.0 to 1.0
+    Random,         // A new random value for every note, -1.0 to 1.0
+}
+
+/// What a mod matrix slot changes. The slot's scaled signal is called `amount` below.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ModDestination {
+    OperatorRatio(usize),    // amount is added to the operator's frequency ratio
+    OperatorGain(usize),     // Output level is scaled by (1 + amount)
+    OperatorModIndex(usize), // Modulation index is scaled by (1 + amount)
+    OperatorPan(usize),      // amount is added to the carrier's pan
+    FilterCutoff,            // amount octaves are added to the ladder and voice filter cutoffs
+    Pan,                     // amount is added to the voice's pan
+    MasterVolume,            // Master volume is scaled by (1 + amount); only follows global sources
+}
+
+/// Shaping applied to the source before it is scaled by the slot amount.
+/// Curves keep the sign, so bipolar sources stay symmetric.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ModCurve {
+    #[default]
+    Linear,
+    Exponential, // Slow start, fast finish
+    Logarithmic, // Fast start, slow finish
+}
+
+impl ModCurve {
+    pub fn apply(&self, value: f32) -> f32 {
+        match self {
+            ModCurve::Linear => value,
+            ModCurve::Exponential => value * value.abs(),
+            ModCurve::Logarithmic => value.abs().sqrt().copysign(value),
+        }
+    }
+}
+
+/// One connection in the mod matrix.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ModSlot {
+    pub source: ModSource,
+    pub destination: ModDestination,
+    pub amount: f32,
+    pub curve: ModCurve,
+}
+
+impl ModSlot {
+    pub fn new(source: ModSource, destination: ModDestination, amount: f32) -> Self {
+        Self {
+            source,
+            destination,
+            amount,
+            curve: ModCurve::Linear,
+        }
+    }
+}
+
+/// A fixed number of slots, each either empty or connecting a source to a destination.
+#[derive(Clone, Debug, Default)]
+pub struct ModMatrix {
+    slots: Vec<Option<ModSlot>>,
+}
+
+impl ModMatrix {
+    pub fn new(slot_count: usize) -> Self {
+        Self {
+            slots: vec![None; slot_count],
+        }
+    }
+
+    pub fn slot_count(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn slot(&self, index: usize) -> Option<&ModSlot> {
+        self.slots.get(index).and_then(Option::as_ref)
+    }
+
+    /// Fills (or with None, clears) a slot.
+    pub fn set_slot(&mut self, index: usize, slot: Option<ModSlot>) -> Result<(), String> {
+        let slot_count = self.slots.len();
+        match self.slots.get_mut(index) {
+            Some(existing) => {
+                *existing = slot;
+                Ok(())
+            }
+            None => Err(format!(
+                "Mod slot {} out of range for {} slots.",
+                index, slot_count
+            )),
+        }
+    }
+
+    /// The filled slots, in slot order.
+    pub fn active_slots(&self) -> impl Iterator<Item = &ModSlot> {
+        self.slots.iter().flatten()
+    }
+}
+
+/// The value of every modulation source for one voice over one buffer.
+/// Missing per-sample buffers read as zero.
+pub struct ModSourceValues<'a> {
+    pub velocity: f32,
+    pub key: f32,
+    pub random: f32,
+    pub envelope: &'a [f32],
+    pub filter_envelope: &'a [f32],
+    pub lfos: &'a [Vec<f32>],
+    pub mod_wheel: f32,
+    pub aftertouch: f32,
+    pub pitch_bend: f32,
+}
+
+impl<'a> ModSourceValues<'a> {
+    /// Only the sources shared by all voices, as seen by engine-wide destinations.
+    pub fn global(shared: &'a SharedModulation) -> Self {
+        Self {
+            velocity: 0.0,
+            key: 0.0,
+            random: 0.0,
+            envelope: &[],
+            filter_envelope: &[],
+            lfos: &shared.lfos,
+            mod_wheel: shared.mod_wheel,
+            aftertouch: shared.aftertouch,
+            pitch_bend: shared.pitch_bend,
+        }
+    }
+
+    /// Writes the source's value for each sample into `output`.
+    pub fn fill(&self, source: ModSource, output: &mut [f32]) {
+        let buffer: &[f32] = match source {
+            ModSource::Velocity => return output.fill(self.velocity),
+            ModSource::Key => return output.fill(self.key),
+            ModSource::Random => return output.fill(self.random),
+            ModSource::ModWheel => return output.fill(self.mod_wheel),
+            ModSource::Aftertouch => return output.fill(self.aftertouch),
+            ModSource::PitchBend => return output.fill(self.pitch_bend),
+            ModSource::Envelope => self.envelope,
+            ModSource::FilterEnvelope => self.filter_envelope,
+            ModSource::Lfo(index) => self.lfos.get(index).map_or(&[], Vec::as_slice),
+        };
+        match buffer.get(..output.len()) {
+            Some(values) => output.copy_from_slice(values),
+            None => output.fill(0.0),
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use super::config::NotePriority;
+use super::note::NoteSource;
+
+/// A key held down (or kept down by a pedal) while playing in mono mode.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HeldNote {
+    pub note_number: u8,
+    pub source: NoteSource,
+    pub frequency: f32,
+    pub velocity: u8,
+    pub sustained: bool, // The key is up, but a pedal keeps the note in the stack
+    pub sostenuto: bool, // Caught by the sostenuto pedal while the key was down
+}
+
+/// The notes held in mono mode, in the order they were pressed.
+/// The voice plays whichever one the note priority picks.
+#[derive(Clone, Debug, Default)]
+pub struct NoteStack {
+    notes: Vec<HeldNote>,
+}
+
+impl NoteStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An empty stack with room for `capacity` notes before it allocates.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            notes: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Adds a note on top of the stack. Pressing a held note again moves it to the top.
+    pub fn push(&mut self, note: HeldNote) {
+        self.remove(note.note_number, note.source);
+        self.notes.push(note);
+    }
+
+    pub fn remove(&mut self, note_number: u8, source: NoteSource) {
+        self.notes
+            .retain(|note| !(note.note_number == note_number && note.source == source));
+    }
+
+    /// The entry for a note, if it is in the stack.
+    pub fn get_mut(&mut self, note_number: u8, source: NoteSource) -> Option<&mut HeldNote> {
+        self.notes
+            .iter_mut()
+            .find(|note| note.note_number == note_number && note.source == source)
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut HeldNote> {
+        self.notes.iter_mut()
+    }
+
+    /// Keeps only the notes for which `keep` returns true, in their pressed order.
+    pub fn retain(&mut self, keep: impl FnMut(&HeldNote) -> bool) {
+        self.notes.retain(keep);
+    }
+
+    pub fn clear(&mut self) {
+        self.notes.clear();
+    }
+
+    /// The note that should be sounding, or None when no keys are held.
+    pub fn current(&self, priority: NotePriority) -> Option<HeldNote> {
+        match priority {
+            NotePriority::Last => self.notes.last(),
+            NotePriority::Low => self.notes.iter().min_by_key(|note| note.note_number),
+            NotePriority::High => self.notes.iter().max_by_key(|note| note.note_number),
+        }
+        .copied()
+    }
+}
@@ -0,0 +1,85 @@
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NoiseColor {
+    White,
+    Pink,  // -3 dB/octave
+    Brown, // -6 dB/octave
+}
+
+/// Fast, seedable noise source. Each voice keeps one per operator so renders are repeatable.
+#[derive(Clone, Debug)]
+pub struct NoiseGenerator {
+    state: u32,
+    pink: [f32; 7], // Filter taps for the pink noise approximation
+    brown: f32,     // Leaky integrator for brown noise
+}
+
+impl NoiseGenerator {
+    pub fn new(seed: u64) -> Self {
+        let mut generator = Self {
+            state: 1,
+            pink: [0.0; 7],
+            brown: 0.0,
+        };
+        generator.seed(seed);
+        generator
+    }
+
+    /// Restarts the sequence from `seed`. The same seed always yields the same samples.
+    pub fn seed(&mut self, seed: u64) {
+        // splitmix64 spreads nearby seeds apart; xorshift32 must never be zero
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        self.state = (z as u32) | 1;
+        self.pink = [0.0; 7];
+        self.brown = 0.0;
+    }
+
+    /// Next white noise sample in [-1.0, 1.0).
+    pub fn next_white(&mut self) -> f32 {
+        // xorshift32
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        // Top 24 bits give an evenly spaced value in [0, 1)
+        (x >> 8) as f32 / (1u32 << 24) as f32 * 2.0 - 1.0
+    }
+
+    pub fn next(&mut self, color: NoiseColor) -> f32 {
+        let white = self.next_white();
+        match color {
+            NoiseColor::White => white,
+            NoiseColor::Pink => {
+                // Paul Kellet's refined pink noise filter
+                let b = &mut self.pink;
+                b[0] = 0.99886 * b[0] + white * 0.0555179;
+                b[1] = 0.99332 * b[1] + white * 0.0750759;
+                b[2] = 0.96900 * b[2] + white * 0.153_852;
+                b[3] = 0.86650 * b[3] + white * 0.3104856;
+                b[4] = 0.55000 * b[4] + white * 0.5329522;
+                b[5] = -0.7616 * b[5] - white * 0.0168980;
+                let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+                b[6] = white * 0.115926;
+                pink * 0.11
+            }
+            NoiseColor::Brown => {
+                self.brown = (self.brown + 0.02 * white) / 1.02;
+                self.brown * 3.5
+            }
+        }
+    }
+}
+
+impl Default for NoiseGenerator {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+/// Derives a distinct seed for one stream (a voice, an operator) from a base seed.
+pub fn derive_seed(seed: u64, stream: u64) -> u64 {
+    seed ^ stream.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+}
@@ -71,6 +71,77 @@ impl fmt::Display for NoteError {
 
 impl std::error::Error for NoteError {}
 
+/// Controller messages for the whole channel, as opposed to a single note.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ControlEvent {
+    PitchBend(f32), // Wheel position from -1.0 (down) to 1.0 (up), scaled by the bend range
+    ControlChange { controller: u8, value: u8 }, // MIDI CC, e.g. CC_MOD_WHEEL
+    ChannelPressure(u8), // Aftertouch for the whole channel (0-127)
+    Tempo(f32),     // Beats per minute, e.g. from a MIDI file's tempo map
+}
+
+/// MIDI controller number of the modulation wheel.
+pub const CC_MOD_WHEEL: u8 = 1;
+/// MIDI controller number of the sustain (damper) pedal. Values of 64 and up are down.
+pub const CC_SUSTAIN: u8 = 64;
+/// MIDI controller number of the sostenuto pedal. Values of 64 and up are down.
+pub const CC_SOSTENUTO: u8 = 66;
+
+/// Everything carried over the engine's event channel.
+#[derive(Debug, Clone, Copy)]
+pub enum ChannelEvent {
+    Note(NoteEvent),
+    Control(ControlEvent),
+}
+
+impl From<NoteEvent> for ChannelEvent {
+    fn from(event: NoteEvent) -> Self {
+        ChannelEvent::Note(event)
+    }
+}
+
+impl From<ControlEvent> for ChannelEvent {
+    fn from(event: ControlEvent) -> Self {
+        ChannelEvent::Control(event)
+    }
+}
+
+/// An event to apply at a given sample of the engine's clock (see `SynthEngine::sample_clock`).
+/// Times that have already passed, like the 0 of events sent without one, apply at the start
+/// of the next buffer.
+#[derive(Debug, Clone, Copy)]
+pub struct TimedEvent {
+    pub time: u64, // Sample position on the engine's clock
+    pub event: ChannelEvent,
+}
+
+impl TimedEvent {
+    pub fn at(time: u64, event: impl Into<ChannelEvent>) -> Self {
+        Self {
+            time,
+            event: event.into(),
+        }
+    }
+}
+
+impl From<ChannelEvent> for TimedEvent {
+    fn from(event: ChannelEvent) -> Self {
+        Self::at(0, event)
+    }
+}
+
+impl From<NoteEvent> for TimedEvent {
+    fn from(event: NoteEvent) -> Self {
+        Self::at(0, event)
+    }
+}
+
+impl From<ControlEvent> for TimedEvent {
+    fn from(event: ControlEvent) -> Self {
+        Self::at(0, event)
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum NoteSource {
     Sequencer,
@@ -1,6 +1,11 @@
-use super::filter::{apply_filter, FilterType};
+use super::envelope::EnvelopeGenerator;
+use super::filter::{Filter, FilterSettings};
+use super::modulation::OperatorModulation;
+use super::noise::{NoiseColor, NoiseGenerator};
 use super::waveform::{Waveform, WaveformGenerator};
+use super::wavetable::Wavetable;
 use std::f32::consts::PI;
+use std::sync::Arc;
 
 #[derive(Clone, Copy, Debug)]
 pub enum CycleDirection {
@@ -19,14 +24,17 @@ pub struct Operator {
     pub frequency: f32,
     pub frequency_ratio: f32, // Ratio relative to the voice's base frequency
     pub fixed_frequency: Option<f32>, // Optional fixed frequency in Hz
-    // TODO: for operator specific envelopes the voice needs to pass the current envelope state, as
-    // well as the time since that state begain, to the Algorithm, which will pass it on to the
-    // operator
-    //
-    // pub envelope: EnvelopeGenerator, // Operator-specific envelope (optional)
+    pub envelope: EnvelopeGenerator, // Each voice runs its own copy in OperatorState
     pub modulation_index: f32,
-    pub gain: f32,          // Output gain of this operator
-    pub filter: FilterType, // Filter applied to this operator's output
+    pub feedback: f32,                  // Feedback depth in SingleSample mode
+    pub gain: f32,                      // Output gain of this operator
+    pub velocity_sensitivity: f32,      // Velocity's effect on level, 0.0-1.0
+    pub filter: Option<FilterSettings>, // Filter on the output (None = bypass)
+    pub pan: f32,                       // Carrier stereo position, -1.0 to 1.0
+    // How strongly LFOs reach this operator (DX7 PMS/AMS-style), 0.0 = not at all
+    pub pitch_mod_sensitivity: f32,
+    pub amp_mod_sensitivity: f32,
+    pub index_mod_sensitivity: f32,
 }
 
 impl Operator {
@@ -34,41 +42,122 @@ impl Operator {
         Self::default()
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn process(
         &self,
-        base_frequency: f32, // Base frequency from the voice/note
+        state: &mut OperatorState,    // Per-voice state for this operator
+        base_frequency: &[f32],       // Per-sample base frequency from the voice/note
+        control: &OperatorModulation, // Per-sample pitch and level changes from the voice
         output: &mut [f32],
-        modulation: &[f32], // Input modulation signal
+        modulation: &[f32],              // Input modulation signal
+        phase_increments: &mut Vec<f32>, // Scratch space, reused between calls
         sample_rate: f32,
-        start_sample_index: u64, // Sample index at the start of this buffer for phase calculation
     ) {
-        // Determine the actual frequency for this operator
-        let actual_frequency = match self.fixed_frequency {
-            Some(fixed_freq) => fixed_freq,
-            None => base_frequency * self.frequency_ratio,
-        };
-
-        // Calculate the phase offset based on the starting sample index
-        let phase_increment = 2.0 * PI * actual_frequency / sample_rate;
-        let phase_offset = (start_sample_index as f32 * phase_increment) % (2.0 * PI);
+        // Advance this voice's phase accumulator; a frequency change only alters the step size,
+        // so the waveform stays continuous.
+        self.fill_phase_increments(
+            base_frequency,
+            control,
+            output.len(),
+            phase_increments,
+            sample_rate,
+        );
 
         // Generate the waveform using the WaveformGenerator
-        self.waveform_generator.generate(
-            actual_frequency,
-            sample_rate,
-            phase_offset,
+        state.phase = self.waveform_generator.generate(
+            state.phase,
+            phase_increments,
             output,
             modulation,
+            &mut state.noise,
         );
 
-        // Apply operator-specific envelope if it exists and is active
-        // self.envelope.apply(output, sample_rate);
+        // Apply the voice's copy of this operator's envelope
+        state.envelope.apply(output, sample_rate);
 
-        // Apply gain
-        apply_gain(output, self.gain);
+        // Apply gain, scaled by velocity. For modulators this also scales modulation depth.
+        apply_gain(output, self.gain * state.velocity_scale);
+        for (sample, gain) in output.iter_mut().zip(&control.gain) {
+            *sample *= gain;
+        }
 
-        // Apply filter
-        //apply_filter(output, self.filter, sample_rate); // Pass filter by value if it's Copy
+        // Apply filter, using this voice's filter memory
+        if let Some(filter) = &self.filter {
+            state.filter.process(filter, output, sample_rate);
+        }
+    }
+
+    /// Prepares `len` samples that are then generated one at a time with `process_sample`,
+    /// as operators in a feedback loop are: fills in the phase step and the output level
+    /// (envelope, gain and velocity) of every sample.
+    #[allow(clippy::too_many_arguments)]
+    pub fn prepare_samples(
+        &self,
+        state: &mut OperatorState,
+        base_frequency: &[f32],
+        control: &OperatorModulation,
+        phase_increments: &mut Vec<f32>,
+        levels: &mut Vec<f32>,
+        len: usize,
+        sample_rate: f32,
+    ) {
+        self.fill_phase_increments(base_frequency, control, len, phase_increments, sample_rate);
+        levels.clear();
+        levels.resize(len, 1.0);
+        state.envelope.apply(levels, sample_rate);
+        apply_gain(levels, self.gain * state.velocity_scale);
+        for (level, gain) in levels.iter_mut().zip(&control.gain) {
+            *level *= gain;
+        }
+    }
+
+    /// Generates one sample prepared by `prepare_samples`, with `modulation` added to the phase.
+    pub fn process_sample(
+        &self,
+        state: &mut OperatorState,
+        phase_increment: f32,
+        level: f32,
+        modulation: f32,
+        sample_rate: f32,
+    ) -> f32 {
+        let mut sample = [0.0];
+        state.phase = self.waveform_generator.generate(
+            state.phase,
+            &[phase_increment],
+            &mut sample,
+            &[modulation],
+            &mut state.noise,
+        );
+        sample[0] *= level;
+        if let Some(filter) = &self.filter {
+            state.filter.process(filter, &mut sample, sample_rate);
+        }
+        sample[0]
+    }
+
+    /// Fills `phase_increments` with the phase step of each of `len` samples
+    fn fill_phase_increments(
+        &self,
+        base_frequency: &[f32],
+        control: &OperatorModulation,
+        len: usize,
+        phase_increments: &mut Vec<f32>,
+        sample_rate: f32,
+    ) {
+        let radians_per_hz = 2.0 * PI / sample_rate;
+        phase_increments.clear();
+        match self.fixed_frequency {
+            Some(fixed_freq) => phase_increments.resize(len, fixed_freq * radians_per_hz),
+            None => phase_increments.extend(
+                base_frequency
+                    .iter()
+                    .take(len)
+                    .map(|frequency| frequency * self.frequency_ratio * radians_per_hz),
+            ),
+        }
+        for (increment, ratio) in phase_increments.iter_mut().zip(&control.frequency) {
+            *increment *= ratio;
+        }
     }
 
     pub fn set_amplitude(&mut self, amp: f32) {
@@ -79,15 +168,12 @@ impl Operator {
     pub fn cycle_waveform(&mut self, direction: CycleDirection) {
         match direction {
             CycleDirection::Forward => {
-                println!("Cycling waveform forward");
                 self.waveform_generator.get_next_waveform();
             }
             CycleDirection::Backward => {
-                println!("Cycling waveform backward");
                 self.waveform_generator.get_previous_waveform();
             }
         };
-        // println!("New waveform: {:?}", self.waveform_generator.waveform); // Access waveform field if needed
     }
 
     // Method to update the waveform directly
@@ -95,6 +181,43 @@ impl Operator {
         println!("Operator waveform set to: {:?}", waveform);
         self.waveform_generator.set_waveform(waveform);
     }
+
+    /// Loads a wavetable into this operator and switches it to the Wavetable waveform.
+    /// The `Arc` lets several operators share one table.
+    pub fn set_wavetable(&mut self, wavetable: Arc<Wavetable>) {
+        println!(
+            "Operator wavetable set ({} frames)",
+            wavetable.frame_count()
+        );
+        self.waveform_generator.set_wavetable(wavetable);
+    }
+
+    /// Moves the wavetable read position across its frames (0.0 = first, 1.0 = last).
+    pub fn set_wavetable_position(&mut self, position: f32) {
+        self.waveform_generator.wavetable_position = position.clamp(0.0, 1.0);
+    }
+
+    /// Sets (or with None, removes) the filter on this operator's output
+    pub fn set_filter(&mut self, filter: Option<FilterSettings>) {
+        println!("Operator filter set to: {:?}", filter);
+        self.filter = filter;
+    }
+
+    /// Places this operator in the stereo field when it is a carrier (-1.0 left to 1.0 right)
+    pub fn set_pan(&mut self, pan: f32) {
+        self.pan = pan.clamp(-1.0, 1.0);
+    }
+
+    /// Sets the spectrum used when this operator plays Waveform::Noise
+    pub fn set_noise_color(&mut self, color: NoiseColor) {
+        println!("Operator noise color set to: {:?}", color);
+        self.waveform_generator.noise_color = color;
+    }
+
+    /// Chooses between the band-limited and naive versions of Square, Sawtooth and Triangle
+    pub fn set_band_limited(&mut self, band_limited: bool) {
+        self.waveform_generator.band_limited = band_limited;
+    }
 }
 
 // Implement Default trait for easy preallocation
@@ -106,9 +229,64 @@ impl Default for Operator {
             frequency_ratio: 1.0,
             fixed_frequency: None, // Default to using ratio
             modulation_index: 1.0,
-            // envelope: EnvelopeGenerator::new(),
+            feedback: 1.0, // Same depth as the modulation index, so the feedback modes compare
+            // Instant attack, full sustain: the voice envelope shapes the note unless changed
+            envelope: EnvelopeGenerator::with_adsr(0.001, 0.1, 1.0, 0.2),
             gain: 1.0,
-            filter: FilterType::LowPass(20000.0), // Default: wide open low-pass
+            velocity_sensitivity: 0.0, // Default: level independent of velocity
+            filter: None,              // Default: unfiltered
+            pan: 0.0,                  // Default: centred
+            pitch_mod_sensitivity: 1.0,
+            amp_mod_sensitivity: 1.0,
+            index_mod_sensitivity: 1.0,
+        }
+    }
+}
+
+/// Per-voice runtime state for a single operator.
+/// The `Operator` holds the shared settings; every voice keeps one of these per operator.
+#[derive(Clone, Debug)]
+pub struct OperatorState {
+    pub envelope: EnvelopeGenerator,
+    pub phase: f32, // Oscillator phase accumulator in radians, wrapped to [0, 2π)
+    pub noise: NoiseGenerator, // Seeded per voice and operator so renders are repeatable
+    pub filter: Filter, // Memory for the operator's filter
+    pub velocity_scale: f32, // Output level from the note velocity and the operator's sensitivity
+    pub feedback_history: [f32; 2], // Last two outputs, oldest first, for single-sample feedback
+}
+
+impl OperatorState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pulls the latest settings from the operator without disturbing the running state.
+    pub fn sync(&mut self, operator: &Operator) {
+        self.envelope.copy_settings_from(&operator.envelope);
+    }
+
+    /// Starts the envelope and resets the phase and filter so every note begins at the same point.
+    pub fn trigger(&mut self) {
+        self.envelope.trigger();
+        self.phase = 0.0;
+        self.filter.reset();
+        self.feedback_history = [0.0; 2];
+    }
+
+    pub fn release(&mut self) {
+        self.envelope.release();
+    }
+}
+
+impl Default for OperatorState {
+    fn default() -> Self {
+        Self {
+            envelope: EnvelopeGenerator::new(),
+            phase: 0.0,
+            noise: NoiseGenerator::default(),
+            filter: Filter::new(),
+            velocity_scale: 1.0,
+            feedback_history: [0.0; 2],
         }
     }
 }
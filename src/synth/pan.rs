@@ -0,0 +1,15 @@
+use std::f32::consts::FRAC_PI_4;
+
+/// Equal-power gains (left, right) for placing a mono signal at `pan`,
+/// from -1.0 (left) through 0.0 (centre, -3 dB per side) to 1.0 (right).
+pub fn pan_gains(pan: f32) -> (f32, f32) {
+    let angle = (pan.clamp(-1.0, 1.0) + 1.0) * FRAC_PI_4;
+    (angle.cos(), angle.sin())
+}
+
+/// Gains (left, right) for shifting an existing stereo signal towards one side.
+/// Centre leaves both channels untouched; fully right silences the left channel.
+pub fn balance_gains(pan: f32) -> (f32, f32) {
+    let pan = pan.clamp(-1.0, 1.0);
+    ((1.0 - pan).min(1.0), (1.0 + pan).min(1.0))
+}
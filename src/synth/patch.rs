@@ -0,0 +1,295 @@
+use super::algorithm::{Algorithm, FeedbackMode};
+use super::config::PlayMode;
+use super::engine::SynthEngine;
+use super::envelope::EnvelopeGenerator;
+use super::glide::{GlideMode, Portamento};
+use super::voice::VelocityCurve;
+use super::waveform::Waveform;
+use std::fs;
+use std::path::Path;
+
+/// An algorithm's connection matrix, carriers and feedback mode, edited while a patch is read
+type AlgorithmParts = (Vec<Vec<Option<usize>>>, Vec<usize>, FeedbackMode);
+
+// A patch is a plain text file of `key = value` lines. Settings before the first section apply
+// to the whole engine; `[operator N]` sections set up operator N. `#` starts a comment.
+//
+//     algorithm = stack_2          # simple, stack_2, feedback_1, dx7 N (1-32) or tx81z N (1-8)
+//     connection = 2 1             # Operator 2 modulates operator 1 (optional third value: N)
+//     carriers = 0
+//     feedback_mode = unrolled     # unrolled or single_sample (dx7 and tx81z set single_sample)
+//     master_volume = 0.65
+//     velocity = soft 0.8          # Curve (linear, soft, hard, fixed) and sensitivity
+//     pitch_bend_range = 2
+//     unison = 3 12 0.5            # Voices, detune in cents, stereo spread
+//     play_mode = mono             # poly or mono
+//     legato = true
+//     portamento = 0.1 constant_time
+//     tempo = 120
+//
+//     [operator 1]
+//     waveform = sine
+//     ratio = 14
+//     fixed_frequency = off        # Or a frequency in Hz
+//     gain = 0.5
+//     modulation_index = 3
+//     feedback = 1.5               # Feedback depth in single_sample mode
+//     envelope = 0.001 1.2 0 0.3   # Attack, decay, sustain, release
+//     velocity_sensitivity = 0.7
+//     pan = 0
+//     band_limited = true
+
+/// Reads a patch file and applies it to `engine`. See `apply_patch` for the format.
+pub fn load_patch<P: AsRef<Path>>(engine: &mut SynthEngine, path: P) -> Result<(), String> {
+    let text = fs::read_to_string(path.as_ref())
+        .map_err(|e| format!("Failed to read {}: {}", path.as_ref().display(), e))?;
+    apply_patch(engine, &text)
+}
+
+/// Applies the settings in a patch to `engine`. Anything the patch doesn't mention is left
+/// as it was. Errors name the offending line.
+pub fn apply_patch(engine: &mut SynthEngine, text: &str) -> Result<(), String> {
+    let operator_count = engine.operators().len();
+    let mut algorithm: Option<AlgorithmParts> = None;
+    let mut operator: Option<usize> = None; // Section being read; None = engine settings
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let result = if let Some(section) = line.strip_prefix('[') {
+            parse_section(section, operator_count).map(|index| operator = Some(index))
+        } else {
+            match line.split_once('=') {
+                Some((key, value)) => match operator {
+                    Some(index) => apply_operator_setting(engine, index, key.trim(), value.trim()),
+                    None => apply_engine_setting(engine, &mut algorithm, key.trim(), value.trim()),
+                },
+                None => Err("Expected `key = value`.".to_string()),
+            }
+        };
+        result.map_err(|e| format!("Patch line {}: {}", number + 1, e))?;
+    }
+
+    if let Some((matrix, carriers, feedback_mode)) = algorithm {
+        let mut algorithm = Algorithm::new(matrix, carriers)?;
+        algorithm.set_feedback_mode(feedback_mode)?;
+        engine.set_algorithm(algorithm)?;
+    }
+    Ok(())
+}
+
+fn parse_section(section: &str, operator_count: usize) -> Result<usize, String> {
+    let name = section
+        .strip_suffix(']')
+        .ok_or("Section header is missing `]`.")?;
+    let index = name
+        .trim()
+        .strip_prefix("operator")
+        .ok_or_else(|| format!("Unknown section `{}`.", name))?;
+    let index: usize = parse_number(index)?;
+    if index >= operator_count {
+        return Err(format!(
+            "Operator {} out of range for {} operators.",
+            index, operator_count
+        ));
+    }
+    Ok(index)
+}
+
+fn apply_engine_setting(
+    engine: &mut SynthEngine,
+    algorithm: &mut Option<AlgorithmParts>,
+    key: &str,
+    value: &str,
+) -> Result<(), String> {
+    let operator_count = engine.operators().len();
+    match key {
+        "algorithm" => {
+            let words: Vec<&str> = value.split_whitespace().collect();
+            let preset = match words[..] {
+                ["simple"] => Algorithm::default_simple(operator_count)?,
+                ["stack_2"] => Algorithm::default_stack_2(operator_count)?,
+                ["feedback_1"] => Algorithm::default_feedback_1(operator_count)?,
+                ["dx7", number] => Algorithm::dx7(parse_number(number)?, operator_count)?,
+                ["tx81z", number] => Algorithm::tx81z(parse_number(number)?, operator_count)?,
+                _ => return Err(format!("Unknown algorithm `{}`.", value)),
+            };
+            *algorithm = Some(parts(&preset));
+        }
+        "connection" => {
+            let numbers: Vec<usize> = parse_numbers(value)?;
+            let (modulator, target, passes) = match numbers[..] {
+                [modulator, target] => (modulator, target, 1),
+                [modulator, target, passes] => (modulator, target, passes),
+                _ => return Err("Expected `modulator target [N]`.".to_string()),
+            };
+            if modulator >= operator_count || target >= operator_count {
+                return Err(format!(
+                    "Connection {} -> {} out of range for {} operators.",
+                    modulator, target, operator_count
+                ));
+            }
+            let (matrix, _, _) = algorithm.get_or_insert_with(|| parts(engine.algorithm()));
+            matrix[target][modulator] = Some(passes);
+        }
+        "carriers" => {
+            let carriers = parse_numbers(value)?;
+            let (_, current, _) = algorithm.get_or_insert_with(|| parts(engine.algorithm()));
+            *current = carriers;
+        }
+        "feedback_mode" => {
+            let mode = match value {
+                "unrolled" => FeedbackMode::Unrolled,
+                "single_sample" => FeedbackMode::SingleSample,
+                _ => return Err(format!("Unknown feedback mode `{}`.", value)),
+            };
+            // An algorithm read earlier in the patch takes the mode with it
+            match algorithm {
+                Some((_, _, current)) => *current = mode,
+                None => engine.set_feedback_mode(mode)?,
+            }
+        }
+        "master_volume" => engine.set_master_volume(parse_number(value)?),
+        "velocity" => {
+            let (curve, sensitivity) = value
+                .split_once(char::is_whitespace)
+                .ok_or("Expected `curve sensitivity`.")?;
+            let curve = match curve {
+                "linear" => VelocityCurve::Linear,
+                "soft" => VelocityCurve::Soft,
+                "hard" => VelocityCurve::Hard,
+                "fixed" => VelocityCurve::Fixed,
+                _ => return Err(format!("Unknown velocity curve `{}`.", curve)),
+            };
+            engine.set_velocity_response(curve, parse_number(sensitivity)?);
+        }
+        "pitch_bend_range" => engine.set_pitch_bend_range(parse_number(value)?),
+        "unison" => {
+            let numbers: Vec<f32> = parse_numbers(value)?;
+            let [voices, detune, spread] = numbers[..] else {
+                return Err("Expected `voices detune spread`.".to_string());
+            };
+            engine.set_unison(voices as usize, detune, spread);
+        }
+        "play_mode" => engine.set_play_mode(match value {
+            "poly" => PlayMode::Poly,
+            "mono" => PlayMode::Mono,
+            _ => return Err(format!("Unknown play mode `{}`.", value)),
+        }),
+        "legato" => engine.set_legato(parse_bool(value)?),
+        "portamento" => {
+            let portamento = match value.split_whitespace().collect::<Vec<_>>()[..] {
+                ["off"] => None,
+                [time] => Some(Portamento::new(
+                    GlideMode::ConstantTime,
+                    parse_number(time)?,
+                )),
+                [time, mode] => {
+                    let mode = match mode {
+                        "constant_time" => GlideMode::ConstantTime,
+                        "constant_rate" => GlideMode::ConstantRate,
+                        _ => return Err(format!("Unknown glide mode `{}`.", mode)),
+                    };
+                    Some(Portamento::new(mode, parse_number(time)?))
+                }
+                _ => return Err("Expected `time [mode]` or `off`.".to_string()),
+            };
+            engine.set_portamento(portamento);
+        }
+        "tempo" => engine.set_tempo(parse_number(value)?),
+        _ => return Err(format!("Unknown setting `{}`.", key)),
+    }
+    Ok(())
+}
+
+fn apply_operator_setting(
+    engine: &mut SynthEngine,
+    index: usize,
+    key: &str,
+    value: &str,
+) -> Result<(), String> {
+    let operator = &mut engine.operators_mut()[index];
+    match key {
+        "waveform" => operator.set_waveform(parse_waveform(value)?),
+        "ratio" => operator.frequency_ratio = parse_number(value)?,
+        "fixed_frequency" => {
+            operator.fixed_frequency = match value {
+                "off" => None,
+                _ => Some(parse_number(value)?),
+            }
+        }
+        "gain" => operator.gain = parse_number(value)?,
+        "modulation_index" => operator.modulation_index = parse_number(value)?,
+        "feedback" => operator.feedback = parse_number(value)?,
+        "envelope" => {
+            let numbers: Vec<f32> = parse_numbers(value)?;
+            let [attack, decay, sustain, release] = numbers[..] else {
+                return Err("Expected `attack decay sustain release`.".to_string());
+            };
+            // A zero time would divide by zero in the envelope and fill the output with NaN
+            if ![attack, decay, release]
+                .iter()
+                .all(|time| time.is_finite() && *time > 0.0)
+            {
+                return Err("Envelope times must be above 0 seconds.".to_string());
+            }
+            if !(0.0..=1.0).contains(&sustain) {
+                return Err("Envelope sustain must be from 0 to 1.".to_string());
+            }
+            operator.envelope = EnvelopeGenerator::with_adsr(attack, decay, sustain, release);
+        }
+        "velocity_sensitivity" => {
+            operator.velocity_sensitivity = parse_number::<f32>(value)?.clamp(0.0, 1.0)
+        }
+        "pan" => operator.set_pan(parse_number(value)?),
+        "band_limited" => operator.set_band_limited(parse_bool(value)?),
+        _ => return Err(format!("Unknown operator setting `{}`.", key)),
+    }
+    Ok(())
+}
+
+fn parts(algorithm: &Algorithm) -> AlgorithmParts {
+    (
+        algorithm.matrix.clone(),
+        algorithm.carriers.clone(),
+        algorithm.feedback_mode(),
+    )
+}
+
+fn parse_waveform(name: &str) -> Result<Waveform, String> {
+    Ok(match name {
+        "sine" => Waveform::Sine,
+        "square" => Waveform::Square,
+        "sawtooth" => Waveform::Sawtooth,
+        "triangle" => Waveform::Triangle,
+        "noise" => Waveform::Noise,
+        "half_sine" => Waveform::HalfSine,
+        "abs_sine" => Waveform::AbsSine,
+        "quarter_sine" => Waveform::QuarterSine,
+        "alternating_sine" => Waveform::AlternatingSine,
+        "camel_sine" => Waveform::CamelSine,
+        "derived_square" => Waveform::DerivedSquare,
+        _ => return Err(format!("Unknown waveform `{}`.", name)),
+    })
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("`{}` is not a valid number.", value.trim()))
+}
+
+fn parse_numbers<T: std::str::FromStr>(value: &str) -> Result<Vec<T>, String> {
+    value.split_whitespace().map(parse_number).collect()
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "true" | "on" | "yes" => Ok(true),
+        "false" | "off" | "no" => Ok(false),
+        _ => Err(format!("`{}` is not true or false.", value)),
+    }
+}
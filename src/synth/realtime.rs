@@ -0,0 +1,117 @@
+use super::engine::SynthEngine;
+use super::note::{ControlEvent, NoteEvent, TimedEvent};
+use super::operator::OperatorEvent;
+use crate::utils::spsc::{spsc_queue, Consumer, Producer};
+use crate::utils::triple_buffer::{triple_buffer, TripleReader, TripleWriter};
+
+/// A message from a control thread to the engine on the audio thread.
+#[derive(Debug, Clone, Copy)]
+pub enum EngineCommand {
+    Event(TimedEvent),
+    Operator(OperatorEvent),
+}
+
+impl From<TimedEvent> for EngineCommand {
+    fn from(event: TimedEvent) -> Self {
+        EngineCommand::Event(event)
+    }
+}
+
+impl From<NoteEvent> for EngineCommand {
+    fn from(event: NoteEvent) -> Self {
+        EngineCommand::Event(event.into())
+    }
+}
+
+impl From<ControlEvent> for EngineCommand {
+    fn from(event: ControlEvent) -> Self {
+        EngineCommand::Event(event.into())
+    }
+}
+
+impl From<OperatorEvent> for EngineCommand {
+    fn from(event: OperatorEvent) -> Self {
+        EngineCommand::Operator(event)
+    }
+}
+
+/// What the audio thread reports about the engine after each buffer.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EngineStatus {
+    pub sample_clock: u64,    // Engine clock after the buffer (TimedEvent time)
+    pub active_voices: usize, // Voices sounding, including releasing ones
+    pub peak: f32,            // Highest absolute sample of the buffer
+}
+
+/// Control-thread end of a `RealtimeEngine`: sends it commands and reads its latest status.
+/// Nothing here blocks or waits on the audio thread.
+pub struct EngineController {
+    commands: Producer<EngineCommand>,
+    status: TripleReader<EngineStatus>,
+}
+
+impl EngineController {
+    /// Queue a command for the start of the engine's next buffer (or, for a `TimedEvent`,
+    /// its own time). The command comes back if the queue is full.
+    pub fn send(&mut self, command: impl Into<EngineCommand>) -> Result<(), EngineCommand> {
+        self.commands.push(command.into())
+    }
+
+    /// The status published after the most recent buffer
+    pub fn status(&mut self) -> EngineStatus {
+        *self.status.read()
+    }
+}
+
+/// A `SynthEngine` owned by the audio thread. Control reaches it through a lock-free queue
+/// from its `EngineController` instead of a mutex, so the audio callback never blocks.
+pub struct RealtimeEngine {
+    engine: SynthEngine,
+    commands: Consumer<EngineCommand>,
+    status: TripleWriter<EngineStatus>,
+}
+
+impl RealtimeEngine {
+    /// Wrap `engine` for the audio thread. The command queue holds
+    /// `config.event_queue_size` commands.
+    pub fn new(engine: SynthEngine) -> (Self, EngineController) {
+        let (producer, consumer) = spsc_queue(engine.config.event_queue_size);
+        let (writer, reader) = triple_buffer(EngineStatus::default());
+        let realtime = Self {
+            engine,
+            commands: consumer,
+            status: writer,
+        };
+        let controller = EngineController {
+            commands: producer,
+            status: reader,
+        };
+        (realtime, controller)
+    }
+
+    /// The engine, e.g. to set it up before the stream starts
+    pub fn engine_mut(&mut self) -> &mut SynthEngine {
+        &mut self.engine
+    }
+
+    /// Apply the queued commands, render an interleaved buffer (see
+    /// `SynthEngine::process_interleaved`) and publish the engine's status.
+    pub fn process_interleaved(&mut self, output: &mut [f32], channels: usize, sample_rate: f32) {
+        let engine = &mut self.engine;
+        while let Some(command) = self.commands.pop() {
+            match command {
+                EngineCommand::Event(event) => engine.queue_event(event),
+                EngineCommand::Operator(event) => engine.apply_operator_event(event),
+            }
+        }
+        engine.process_interleaved(output, channels, sample_rate);
+
+        let active_voices = engine.voices.iter().filter(|v| !v.is_finished()).count();
+        let peak = output.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        self.status.write(EngineStatus {
+            sample_clock: engine.sample_clock(),
+            active_voices,
+            peak,
+        });
+    }
+}
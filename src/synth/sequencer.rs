@@ -0,0 +1,302 @@
+use super::note::{ChannelEvent, ControlEvent, NoteEvent, NoteSource, CC_SOSTENUTO, CC_SUSTAIN};
+use crate::utils::midi_file::{read_midi_file, Division, MidiFile, MidiMessage};
+use std::path::Path;
+
+/// Tempo used until a MIDI file sets its own, in microseconds per quarter note (120 BPM).
+const DEFAULT_TEMPO: u32 = 500_000;
+
+/// Converts MIDI ticks to seconds, following the tempo changes of a file.
+#[derive(Clone, Debug)]
+pub struct TempoMap {
+    division: Division,
+    // (tick, seconds at that tick, microseconds per quarter from there on), sorted by tick
+    segments: Vec<(u64, f64, u32)>,
+}
+
+impl TempoMap {
+    /// Builds the map from every tempo event in the file, whichever track it is on.
+    pub fn new(file: &MidiFile) -> Self {
+        let mut changes: Vec<(u64, u32)> = file
+            .tracks
+            .iter()
+            .flatten()
+            .filter_map(|event| match event.message {
+                MidiMessage::Tempo(tempo) if tempo > 0 => Some((event.tick, tempo)),
+                _ => None,
+            })
+            .collect();
+        changes.sort_by_key(|&(tick, _)| tick);
+
+        let mut map = Self {
+            division: file.division,
+            segments: vec![(0, 0.0, DEFAULT_TEMPO)],
+        };
+        for (tick, tempo) in changes {
+            let seconds = map.seconds_at(tick);
+            match map.segments.last_mut() {
+                // A later change at the same tick wins
+                Some(last) if last.0 == tick => last.2 = tempo,
+                _ => map.segments.push((tick, seconds, tempo)),
+            }
+        }
+        map
+    }
+
+    /// Time of `tick` in seconds from the start of the file.
+    pub fn seconds_at(&self, tick: u64) -> f64 {
+        match self.division {
+            Division::TicksPerQuarter(ticks_per_quarter) => {
+                let index = self
+                    .segments
+                    .partition_point(|&(start, _, _)| start <= tick)
+                    .saturating_sub(1);
+                let (start, seconds, tempo) = self.segments[index];
+                let quarters = (tick - start) as f64 / ticks_per_quarter as f64;
+                seconds + quarters * tempo as f64 / 1_000_000.0
+            }
+            Division::Smpte {
+                frames_per_second,
+                ticks_per_frame,
+            } => {
+                let frames_per_second = match frames_per_second {
+                    29 => 29.97,
+                    fps => fps as f64,
+                };
+                tick as f64 / (frames_per_second * ticks_per_frame as f64)
+            }
+        }
+    }
+
+    /// Tempo in beats per minute at `tick`.
+    pub fn bpm_at(&self, tick: u64) -> f32 {
+        let index = self
+            .segments
+            .partition_point(|&(start, _, _)| start <= tick)
+            .saturating_sub(1);
+        60_000_000.0 / self.segments[index].2 as f32
+    }
+}
+
+/// A file event ready to send to the engine.
+#[derive(Clone, Copy, Debug)]
+struct SequencedEvent {
+    tick: u64,
+    seconds: f64,
+    event: ChannelEvent,
+}
+
+/// Plays a Standard MIDI File into the `SynthEngine`. The engine advances it by each audio
+/// buffer, so events land on their exact sample rather than following the wall clock.
+/// Notes are sent with `NoteSource::Sequencer` and every MIDI channel plays the same patch.
+#[derive(Clone, Debug)]
+pub struct Sequencer {
+    events: Vec<SequencedEvent>, // All tracks merged, in time order
+    tempo_map: TempoMap,
+    loop_points: Option<(u64, u64)>, // Start and end tick of the loop
+    position: f64,                   // Playback position in seconds
+    next_event: usize,               // Index of the first event not yet sent
+    notes_on: [u8; 128],             // Note-ons without their note-off yet, per note number
+}
+
+impl Sequencer {
+    /// Prepares `file` for playback from the start.
+    pub fn new(file: &MidiFile) -> Self {
+        let tempo_map = TempoMap::new(file);
+        let mut events: Vec<SequencedEvent> = file
+            .tracks
+            .iter()
+            .flatten()
+            .filter_map(|event| {
+                Some(SequencedEvent {
+                    tick: event.tick,
+                    seconds: tempo_map.seconds_at(event.tick),
+                    event: to_channel_event(&event.message, &tempo_map, event.tick)?,
+                })
+            })
+            .collect();
+        // Stable, so events on the same tick keep their track order
+        events.sort_by_key(|event| event.tick);
+
+        Self {
+            events,
+            tempo_map,
+            loop_points: None,
+            position: 0.0,
+            next_event: 0,
+            notes_on: [0; 128],
+        }
+    }
+
+    /// Reads and prepares a MIDI file. See `read_midi_file`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        Ok(Self::new(&read_midi_file(path)?))
+    }
+
+    pub fn tempo_map(&self) -> &TempoMap {
+        &self.tempo_map
+    }
+
+    /// Tick of the last event in the file.
+    pub fn length_ticks(&self) -> u64 {
+        self.events.last().map_or(0, |event| event.tick)
+    }
+
+    /// Time of the last event in the file, in seconds.
+    pub fn duration(&self) -> f64 {
+        self.events.last().map_or(0.0, |event| event.seconds)
+    }
+
+    /// Playback position in seconds.
+    pub fn position(&self) -> f64 {
+        self.position
+    }
+
+    /// Whether every event has been sent and no loop will bring playback back.
+    pub fn is_finished(&self) -> bool {
+        self.loop_points.is_none() && self.next_event >= self.events.len()
+    }
+
+    /// Loop playback between two ticks (or with None, play through once). Reaching the end
+    /// tick releases all sequenced notes and jumps back to the start tick.
+    pub fn set_loop(&mut self, loop_points: Option<(u64, u64)>) -> Result<(), String> {
+        if let Some((start, end)) = loop_points {
+            if start >= end {
+                return Err(format!(
+                    "Loop start tick {} must be before the end tick {}.",
+                    start, end
+                ));
+            }
+        }
+        self.loop_points = loop_points;
+        Ok(())
+    }
+
+    /// Jump to `tick`. Sounding notes are released first.
+    pub fn seek(&mut self, tick: u64, emit: impl FnMut(ChannelEvent)) {
+        self.all_notes_off(emit);
+        self.position = self.tempo_map.seconds_at(tick);
+        self.next_event = self.events.partition_point(|event| event.tick < tick);
+    }
+
+    /// Advance playback by `frames` samples, passing every event that falls within them
+    /// to `emit` in order, along with the frame it falls on (counted from the first frame).
+    pub fn advance(
+        &mut self,
+        frames: usize,
+        sample_rate: f32,
+        mut emit: impl FnMut(usize, ChannelEvent),
+    ) {
+        let sample_rate = sample_rate as f64;
+        let last_frame = frames.saturating_sub(1);
+        let to_frame = |seconds: f64| ((seconds * sample_rate).round() as usize).min(last_frame);
+        // Position is summed buffer by buffer, so it can land a hair before an event that is
+        // due exactly on the next buffer's first frame; such events wait for that buffer
+        let in_buffer = |seconds: f64| (seconds * sample_rate).round() < frames as f64;
+        let mut remaining = frames as f64 / sample_rate;
+        let mut elapsed = 0.0; // Seconds of the span played before this pass through the loop
+        loop {
+            // Loop points only apply while playback is before the loop end
+            let loop_seconds = self.loop_points.and_then(|(start, end)| {
+                let end_seconds = self.tempo_map.seconds_at(end);
+                (self.position < end_seconds)
+                    .then(|| (start, self.tempo_map.seconds_at(start), end_seconds))
+            });
+            let window_end = match loop_seconds {
+                Some((_, _, end_seconds)) => (self.position + remaining).min(end_seconds),
+                None => self.position + remaining,
+            };
+
+            while let Some(&event) = self.events.get(self.next_event) {
+                if event.seconds >= window_end
+                    || !in_buffer(elapsed + event.seconds - self.position)
+                {
+                    break;
+                }
+                self.track_note(&event.event);
+                emit(
+                    to_frame(elapsed + event.seconds - self.position),
+                    event.event,
+                );
+                self.next_event += 1;
+            }
+            remaining -= window_end - self.position;
+            elapsed += window_end - self.position;
+            self.position = window_end;
+
+            match loop_seconds {
+                Some((start, start_seconds, end_seconds)) if self.position >= end_seconds => {
+                    let frame = to_frame(elapsed);
+                    self.all_notes_off(|event| emit(frame, event));
+                    self.position = start_seconds;
+                    self.next_event = self.events.partition_point(|event| event.tick < start);
+                    if remaining <= 0.0 {
+                        break;
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    /// Send note-offs for every note the sequencer started, and lift the pedals, so nothing
+    /// is left hanging when playback jumps or stops.
+    pub fn all_notes_off(&mut self, mut emit: impl FnMut(ChannelEvent)) {
+        for note in 0..128u8 {
+            for _ in 0..std::mem::take(&mut self.notes_on[note as usize]) {
+                if let Ok(event) = NoteEvent::new(note, 0, false, NoteSource::Sequencer) {
+                    emit(event.into());
+                }
+            }
+        }
+        for controller in [CC_SUSTAIN, CC_SOSTENUTO] {
+            emit(
+                ControlEvent::ControlChange {
+                    controller,
+                    value: 0,
+                }
+                .into(),
+            );
+        }
+        emit(ControlEvent::PitchBend(0.0).into());
+    }
+
+    fn track_note(&mut self, event: &ChannelEvent) {
+        if let ChannelEvent::Note(note) = event {
+            let count = &mut self.notes_on[note.note_number as usize];
+            if note.is_on {
+                // Saturating: drum tracks may send note-ons only, and 255 note-offs still
+                // release more voices than the engine has
+                *count = count.saturating_add(1);
+            } else {
+                *count = count.saturating_sub(1);
+            }
+        }
+    }
+}
+
+/// The engine event for a file message, or None for messages the engine doesn't take.
+fn to_channel_event(
+    message: &MidiMessage,
+    tempo_map: &TempoMap,
+    tick: u64,
+) -> Option<ChannelEvent> {
+    let event = match *message {
+        MidiMessage::NoteOn { note, velocity, .. } => {
+            NoteEvent::new(note, velocity, true, NoteSource::Sequencer)
+                .ok()?
+                .into()
+        }
+        MidiMessage::NoteOff { note, .. } => NoteEvent::new(note, 0, false, NoteSource::Sequencer)
+            .ok()?
+            .into(),
+        MidiMessage::ControlChange {
+            controller, value, ..
+        } => ControlEvent::ControlChange { controller, value }.into(),
+        MidiMessage::PitchBend { value, .. } => {
+            ControlEvent::PitchBend((value as f32 / 8192.0).clamp(-1.0, 1.0)).into()
+        }
+        MidiMessage::ChannelPressure { value, .. } => ControlEvent::ChannelPressure(value).into(),
+        MidiMessage::Tempo(_) => ControlEvent::Tempo(tempo_map.bpm_at(tick)).into(),
+    };
+    Some(event)
+}
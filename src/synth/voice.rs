@@ -1,16 +1,112 @@
-use super::algorithm::Algorithm;
+use super::algorithm::{Algorithm, AlgorithmBuffers};
 use super::envelope::EnvelopeGenerator;
+use super::filter::{Filter, FilterSettings, LadderFilter, LadderSettings};
+use super::glide::{Glide, Portamento};
+use super::lfo::{Lfo, LfoSettings};
+use super::modulation::{
+    ModDestination, ModMatrix, ModSourceValues, OperatorModulation, SharedModulation,
+    VoiceModulation,
+};
+use super::noise::{derive_seed, NoiseGenerator};
 use super::note::NoteSource;
-use super::operator::Operator;
+use super::operator::{Operator, OperatorState};
+use super::pan::balance_gains;
+
+/// Maps MIDI velocity to a 0.0-1.0 level.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VelocityCurve {
+    Linear,
+    Soft,  // Concave: quiet playing already gets most of the level
+    Hard,  // Convex: needs a firm touch to reach full level
+    Fixed, // Ignores velocity, always full level
+}
+
+impl VelocityCurve {
+    pub fn apply(&self, velocity: u8) -> f32 {
+        let v = velocity.min(127) as f32 / 127.0;
+        match self {
+            VelocityCurve::Linear => v,
+            VelocityCurve::Soft => v.sqrt(),
+            VelocityCurve::Hard => v * v,
+            VelocityCurve::Fixed => 1.0,
+        }
+    }
+}
+
+/// Voice-level settings shared by every voice. Owned by the SynthEngine and passed to
+/// `Voice::process`; per-voice running state stays in the `Voice`.
+#[derive(Clone, Debug)]
+pub struct VoiceParams {
+    pub filter: Option<FilterSettings>, // Filter on the voice's carrier output (both channels)
+    pub ladder: Option<LadderSettings>, // Resonant ladder stage right after the algorithm
+    pub velocity_curve: VelocityCurve,  // Shared by the voice level and operator sensitivities
+    pub velocity_sensitivity: f32,      // 0.0 = velocity doesn't change voice level, 1.0 = full
+    pub portamento: Option<Portamento>, // Glide between notes a voice moves through (mono mode)
+    pub lfos: Vec<LfoSettings>,         // Per-voice and global LFOs
+    pub mod_matrix: ModMatrix,          // Routings from modulation sources to voice parameters
+}
+
+impl Default for VoiceParams {
+    fn default() -> Self {
+        Self {
+            filter: None,
+            ladder: None,
+            velocity_curve: VelocityCurve::Linear,
+            velocity_sensitivity: 1.0,
+            portamento: None,
+            lfos: Vec::new(),
+            mod_matrix: ModMatrix::default(),
+        }
+    }
+}
+
+/// Where a voice sits within a unison stack.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct UnisonOffset {
+    pub detune: f32, // Pitch offset in cents
+    pub pan: f32,    // Stereo position, -1.0 (left) to 1.0 (right)
+}
+
+/// A note waiting for a stolen voice to finish fading out.
+#[derive(Clone, Copy, Debug)]
+struct PendingNote {
+    note_number: u8,
+    note_source: Option<NoteSource>,
+    note_frequency: f32,
+    velocity: u8,
+    unison: UnisonOffset,
+    released: bool, // Note-off arrived before the note got to start
+}
 
 /// Represents a single polyphonic voice in the synthesizer.
 pub struct Voice {
-    pub active: bool,                    // Is the voice currently playing a note?
-    pub note_number: u8,                 // MIDI note number (0-127)
-    pub note_frequency: f32,             // Frequency derived from note_number
-    pub note_source: Option<NoteSource>, // Where the note came from (keyboard, sequencer)
-    envelope: EnvelopeGenerator,         // Main amplitude envelope for the voice
-    samples_elapsed_since_trigger: u64,  // Counter for phase calculation
+    pub active: bool,                       // Is the voice currently playing a note?
+    pub note_number: u8,                    // MIDI note number (0-127)
+    pub note_frequency: f32,                // Frequency of note_number; the glide target
+    pub velocity: u8,                       // MIDI velocity (0-127) the note was played with
+    pub note_id: u64,                       // Increasing id from the engine; lower = older
+    pub note_source: Option<NoteSource>,    // Where the note came from (keyboard, sequencer)
+    pub unison: UnisonOffset,               // Detune and pan within the note's unison stack
+    pub sustained: bool,                    // The key is up, but a pedal holds the note
+    pub sostenuto: bool,                    // Caught by the sostenuto pedal while held
+    envelope: EnvelopeGenerator,            // Main amplitude envelope for the voice
+    operator_states: Vec<OperatorState>,    // Per-operator envelopes and phases
+    noise_seed: u64,                        // Seed for the operator noise generators
+    filter: [Filter; 2],                    // Voice-level filter memory, per channel
+    ladder: [LadderFilter; 2],              // Ladder stage memory, per channel
+    filter_envelope: EnvelopeGenerator,     // Drives the ladder cutoff
+    pending_note: Option<PendingNote>,      // Note to start once the steal fade-out ends
+    fade_remaining: usize,                  // Samples left in the steal fade-out
+    fade_length: usize,                     // Total length of the steal fade-out
+    glide: Glide,                           // Pitch played while moving to note_frequency
+    lfos: Vec<Lfo>,                         // State of the per-voice LFOs in VoiceParams::lfos
+    lfo_trigger_pending: bool,              // Restart the LFOs on the next render (new note)
+    lfo_values: Vec<Vec<f32>>,              // Each per-voice LFO's output for this buffer
+    operator_mods: Vec<OperatorModulation>, // Per-operator modulation for the current buffer
+    random: NoiseGenerator,                 // Draws the per-note random mod source
+    note_random: f32,                       // Random mod source for this note (-1.0 to 1.0)
+    buffers: RenderBuffers,                 // Scratch for `render`, so it doesn't allocate
+    fade_buffers: [Vec<f32>; 2],            // Output of a stolen note while it fades out
 }
 
 impl Voice {
@@ -20,36 +116,178 @@ impl Voice {
     }
 
     /// Activates the voice for a given note.
-    /// Resets the sample counter and triggers the envelope.
+    /// Triggers the envelopes and resets the operator phases.
     pub fn activate(
         &mut self,
         note_number: u8,
         note_source: Option<NoteSource>,
         note_frequency: f32,
+        velocity: u8,
+    ) {
+        self.start_note(note_number, note_source, note_frequency, velocity, false);
+    }
+
+    /// Like `activate`, but if the voice is still sounding the pitch glides from the
+    /// current note instead of jumping (mono mode without legato).
+    pub fn retrigger(
+        &mut self,
+        note_number: u8,
+        note_source: Option<NoteSource>,
+        note_frequency: f32,
+        velocity: u8,
     ) {
+        let glide = !self.is_finished();
+        self.sustained = false;
+        self.sostenuto = false;
+        self.start_note(note_number, note_source, note_frequency, velocity, glide);
+    }
+
+    /// Moves a sounding voice to a new note without retriggering its envelopes (legato).
+    pub fn legato_to(&mut self, note_number: u8, note_frequency: f32) {
+        self.note_number = note_number;
+        self.note_frequency = note_frequency;
+        self.glide.glide_to(note_frequency);
+    }
+
+    fn start_note(
+        &mut self,
+        note_number: u8,
+        note_source: Option<NoteSource>,
+        note_frequency: f32,
+        velocity: u8,
+        glide: bool,
+    ) {
+        if glide {
+            self.glide.glide_to(note_frequency);
+        } else {
+            self.glide.jump_to(note_frequency);
+        }
+        self.pending_note = None;
+        self.fade_remaining = 0;
+        self.lfo_trigger_pending = true;
+        self.note_random = self.random.next_white();
         self.active = true;
         self.note_number = note_number;
         self.note_source = note_source;
         self.note_frequency = note_frequency;
-        self.samples_elapsed_since_trigger = 0;
+        self.velocity = velocity;
         self.envelope.trigger();
 
-        println!(
-            "Voice activated note {}, sample counter reset",
-            self.note_number
-        );
         // Trigger the main envelope
-        // TODO: pass envelope events to the operator when processing to trigger operator envelopes
         self.envelope.trigger();
+        self.filter.iter_mut().for_each(Filter::reset);
+        self.ladder.iter_mut().for_each(LadderFilter::reset);
+        self.filter_envelope.trigger();
+        // Trigger the operator envelopes alongside it
+        for state in self.operator_states.iter_mut() {
+            state.trigger();
+        }
+    }
+
+    /// Takes over this voice for a new note. If it is still sounding, the current sound fades
+    /// out over `fade_samples` first and the new note starts right after, avoiding a click.
+    pub fn steal(
+        &mut self,
+        note_number: u8,
+        note_source: Option<NoteSource>,
+        note_frequency: f32,
+        velocity: u8,
+        unison: UnisonOffset,
+        fade_samples: usize,
+    ) {
+        // The new note's key is down; pedals only catch it from here on
+        self.sustained = false;
+        self.sostenuto = false;
+        if self.is_finished() || fade_samples == 0 {
+            self.activate(note_number, note_source, note_frequency, velocity);
+            self.unison = unison;
+            return;
+        }
+        self.fade_out(fade_samples);
+        self.pending_note = Some(PendingNote {
+            note_number,
+            note_source,
+            note_frequency,
+            velocity,
+            unison,
+            released: false,
+        });
+    }
+
+    /// Fades the voice to silence over `fade_samples`, e.g. when its unison group is stolen.
+    /// Cancels any note that was waiting to start on it.
+    pub fn fade_out(&mut self, fade_samples: usize) {
+        self.pending_note = None;
+        if fade_samples == 0 {
+            self.stop();
+        } else if self.fade_remaining == 0 {
+            // A voice that is already fading keeps its current fade
+            self.fade_remaining = fade_samples;
+            self.fade_length = fade_samples;
+        }
+    }
+
+    /// Silences the voice immediately.
+    fn stop(&mut self) {
+        self.active = false;
+        self.fade_remaining = 0;
+        self.envelope.reset();
+        self.filter_envelope.reset();
+    }
+
+    /// Whether this voice is sounding (or about to sound) the given note from the given source.
+    pub fn plays_note(&self, note_number: u8, note_source: Option<NoteSource>) -> bool {
+        match &self.pending_note {
+            Some(pending) => {
+                pending.note_number == note_number && pending.note_source == note_source
+            }
+            None => {
+                // A voice fading out has already been given up
+                self.fade_remaining == 0
+                    && (!self.is_finished() || self.active)
+                    && self.note_number == note_number
+                    && self.note_source == note_source
+            }
+        }
+    }
+
+    /// Whether the key of the voice's note (or the note waiting to start) is still down.
+    pub fn is_key_down(&self) -> bool {
+        if self.sustained {
+            return false;
+        }
+        match &self.pending_note {
+            Some(pending) => !pending.released,
+            None => self.active && self.fade_remaining == 0,
+        }
+    }
+
+    /// Whether the note has been released and the voice is only finishing its release phase.
+    pub fn is_releasing(&self) -> bool {
+        !self.active && self.pending_note.is_none() && !self.is_finished()
+    }
+
+    /// Current level of the main envelope (0.0-1.0).
+    pub fn level(&self) -> f32 {
+        self.envelope.value
     }
 
     /// Initiates the release phase of the voice's main envelope.
     pub fn release(&mut self) {
+        if let Some(pending) = &mut self.pending_note {
+            // The old note is already fading out; release the new one as soon as it starts
+            pending.released = true;
+            return;
+        }
+
         // Check if the voice is actually active OR the envelope is still running before releasing.
         // Avoids re-releasing if multiple note-offs are received or if already released.
         if self.active || !self.envelope.is_finished() {
-            println!("Voice releasing envelope for note {}", self.note_number);
             self.envelope.release();
+            self.filter_envelope.release();
+            for state in self.operator_states.iter_mut() {
+                state.release();
+            }
 
             // Mark the voice as inactive (no longer accepting triggers),
             // but it will continue processing until the envelope finishes its release phase.
@@ -60,13 +298,97 @@ impl Voice {
     /// Processes a buffer of audio for this voice using the provided algorithm and operators.
     /// `algorithm`: The FM algorithm defining operator connections.
     /// `operators`: The set of operators configured in the SynthEngine.
-    /// `output`: The buffer to add this voice's contribution to.
+    /// `params`: Voice-level settings from the SynthEngine.
+    /// `shared`: Per-buffer signals from the SynthEngine, such as the global LFOs.
+    /// `output_left`, `output_right`: The stereo buffers to add this voice's contribution to.
     /// `sample_rate`: The audio sample rate.
+    #[allow(clippy::too_many_arguments)]
     pub fn process(
+        &mut self,
+        algorithm: &Algorithm,
+        operators: &[Operator],
+        params: &VoiceParams,
+        shared: &SharedModulation,
+        output_left: &mut [f32],
+        output_right: &mut [f32],
+        sample_rate: f32,
+    ) {
+        if self.fade_remaining == 0 {
+            self.render(
+                algorithm,
+                operators,
+                params,
+                shared,
+                0,
+                [output_left, output_right],
+                sample_rate,
+            );
+            return;
+        }
+
+        // The voice was stolen: fade out the old note, then start the pending one (if any)
+        let fade_len = self.fade_remaining.min(output_left.len());
+        let (fading_left, rest_left) = output_left.split_at_mut(fade_len);
+        let (fading_right, rest_right) = output_right.split_at_mut(fade_len);
+        let [mut faded_left, mut faded_right] = std::mem::take(&mut self.fade_buffers);
+        fill_buffer(&mut faded_left, fade_len, 0.0);
+        fill_buffer(&mut faded_right, fade_len, 0.0);
+        self.render(
+            algorithm,
+            operators,
+            params,
+            shared,
+            0,
+            [&mut faded_left, &mut faded_right],
+            sample_rate,
+        );
+        for i in 0..fade_len {
+            let gain = (self.fade_remaining - i) as f32 / self.fade_length as f32;
+            fading_left[i] += faded_left[i] * gain;
+            fading_right[i] += faded_right[i] * gain;
+        }
+        self.fade_buffers = [faded_left, faded_right];
+        self.fade_remaining -= fade_len;
+
+        if self.fade_remaining == 0 {
+            match self.pending_note.take() {
+                Some(pending) => {
+                    self.activate(
+                        pending.note_number,
+                        pending.note_source,
+                        pending.note_frequency,
+                        pending.velocity,
+                    );
+                    self.unison = pending.unison;
+                    if pending.released {
+                        self.release();
+                    }
+                    self.render(
+                        algorithm,
+                        operators,
+                        params,
+                        shared,
+                        fade_len,
+                        [rest_left, rest_right],
+                        sample_rate,
+                    );
+                }
+                None => self.stop(),
+            }
+        }
+    }
+
+    /// Renders this voice's current note into the `[left, right]` buffers (additive).
+    /// `offset` is where the output starts within the engine's buffer, to line up `shared`.
+    #[allow(clippy::too_many_arguments)]
+    fn render(
         &mut self,
         algorithm: &Algorithm,  // Pass algorithm
         operators: &[Operator], // Pass operators slice
-        output: &mut [f32],     // Note: This should likely be additive or cleared upstream
+        params: &VoiceParams,
+        shared: &SharedModulation,
+        offset: usize,
+        output: [&mut [f32]; 2], // Note: This should likely be additive or cleared upstream
         sample_rate: f32,
     ) {
         // If the voice is fully finished (inactive AND envelope done), skip processing.
@@ -77,58 +399,301 @@ impl Voice {
             return;
         }
 
-        let buffer_len = output.len();
+        let buffer_len = output[0].len();
         if buffer_len == 0 {
             return; // Nothing to process
         }
 
-        // Store the sample index corresponding to the START of this buffer.
-        let start_sample_index = self.samples_elapsed_since_trigger;
+        // Make sure there is one state per operator, carrying the latest operator settings.
+        let velocity = params.velocity_curve.apply(self.velocity);
+        self.sync_operator_states(operators, velocity);
+
+        // Borrow the scratch buffers for this render; they go back at the end
+        let mut buffers = std::mem::take(&mut self.buffers);
+        let RenderBuffers {
+            frequencies,
+            envelope,
+            filter_envelope,
+            raw: [raw_left, raw_right],
+            voice_mods,
+            mod_buffers,
+            algorithm: algorithm_buffers,
+        } = &mut buffers;
+
+        // Per-sample pitch, moving towards note_frequency when portamento is on
+        fill_buffer(frequencies, buffer_len, 0.0);
+        self.glide
+            .process(params.portamento.as_ref(), frequencies, sample_rate);
+        if self.unison.detune != 0.0 {
+            let detune_ratio = (self.unison.detune / 1200.0).exp2();
+            for frequency in frequencies.iter_mut() {
+                *frequency *= detune_ratio;
+            }
+        }
+        // Pitch bend retunes every ratio-based operator; the phases carry on untouched
+        if let Some(bend) = shared.bend.get(offset..offset + buffer_len) {
+            for (frequency, ratio) in frequencies.iter_mut().zip(bend) {
+                *frequency *= ratio;
+            }
+        }
+
+        // --- Envelopes ---
+        // Rendered up front so the mod matrix can use them as sources.
+        fill_buffer(envelope, buffer_len, 1.0);
+        self.envelope.apply(envelope, sample_rate);
+        filter_envelope.clear();
+        if let Some(ladder) = &params.ladder {
+            self.filter_envelope.copy_settings_from(&ladder.envelope);
+            filter_envelope.resize(buffer_len, 1.0);
+            self.filter_envelope.apply(filter_envelope, sample_rate);
+        }
+
+        // --- Apply LFOs and the Mod Matrix ---
+        self.update_lfos(params, shared, offset, buffer_len, sample_rate);
+        let sources = ModSourceValues {
+            velocity,
+            key: self.note_number.min(127) as f32 / 127.0,
+            random: self.note_random,
+            envelope,
+            filter_envelope,
+            lfos: &self.lfo_values,
+            mod_wheel: shared.mod_wheel,
+            aftertouch: shared.aftertouch,
+            pitch_bend: shared.pitch_bend,
+        };
+        update_operator_mods(
+            &mut self.operator_mods,
+            voice_mods,
+            mod_buffers,
+            operators,
+            params,
+            &sources,
+            buffer_len,
+        );
 
         // --- Generate Raw Audio using Algorithm and Operators ---
-        // Create a temporary buffer for the raw operator output before enveloping.
-        let mut raw_output = vec![0.0; buffer_len];
+        // Render into the raw stereo buffers for the raw operator output before enveloping.
+        fill_buffer(raw_left, buffer_len, 0.0);
+        fill_buffer(raw_right, buffer_len, 0.0);
+        let modulation = VoiceModulation {
+            base_frequency: frequencies,
+            operators: &self.operator_mods,
+        };
         algorithm.process(
             operators, // Pass the operators slice
-            self.note_frequency,
-            &mut raw_output, // Generate into the temporary buffer
+            &mut self.operator_states,
+            &modulation,
+            algorithm_buffers,
+            raw_left, // Generate into the temporary buffers
+            raw_right,
             sample_rate,
-            start_sample_index,
         );
 
-        // --- Apply Main Voice Envelope ---
-        // Apply the overall envelope to the raw generated sound.
-        self.envelope.apply(&mut raw_output, sample_rate); // Apply modifies raw_output in place
+        // --- Apply Ladder Filter ---
+        if let Some(ladder) = &params.ladder {
+            // Envelope level per sample, turned into a per-sample cutoff shared by both channels
+            let cutoff = filter_envelope;
+            for value in cutoff.iter_mut() {
+                *value = ladder.modulated_cutoff(*value, self.note_frequency, velocity);
+            }
+            for (value, octaves) in cutoff.iter_mut().zip(&voice_mods.cutoff) {
+                *value *= octaves.exp2();
+            }
+            for (stage, raw) in self
+                .ladder
+                .iter_mut()
+                .zip([&mut *raw_left, &mut *raw_right])
+            {
+                stage.process(raw, cutoff, ladder.resonance, ladder.drive, sample_rate);
+            }
+        }
+
+        // --- Apply Voice Filter ---
+        if let Some(filter) = &params.filter {
+            if voice_mods.cutoff.is_empty() {
+                for (state, raw) in self
+                    .filter
+                    .iter_mut()
+                    .zip([&mut *raw_left, &mut *raw_right])
+                {
+                    state.process(filter, raw, sample_rate);
+                }
+            } else {
+                // The biquad recomputes its coefficients when the settings change, so a
+                // modulated cutoff is followed once per block rather than every sample.
+                let mut start = 0;
+                while start < buffer_len {
+                    let end = (start + FILTER_MOD_BLOCK).min(buffer_len);
+                    let mut settings = *filter;
+                    settings.cutoff *= voice_mods.cutoff[start].exp2();
+                    for (state, raw) in self
+                        .filter
+                        .iter_mut()
+                        .zip([&mut *raw_left, &mut *raw_right])
+                    {
+                        state.process(&settings, &mut raw[start..end], sample_rate);
+                    }
+                    start = end;
+                }
+            }
+        }
 
         // --- Add to Final Output ---
-        // Add the enveloped sound of this voice to the main output buffer.
-        // Assumes the main output buffer might contain other voices.
+        // Add the enveloped sound of this voice to the main output buffers, scaled by velocity
+        // and balanced by the voice's unison pan.
+        // Assumes the main output buffers might contain other voices.
+        let velocity_gain = velocity_scale(params.velocity_sensitivity, velocity);
+        let (mut left_gain, mut right_gain) = balance_gains(self.unison.pan);
+        let [output_left, output_right] = output;
         for i in 0..buffer_len {
-            output[i] += raw_output[i]; // Additive mixing
+            if let Some(offset) = voice_mods.pan.get(i) {
+                (left_gain, right_gain) =
+                    balance_gains((self.unison.pan + offset).clamp(-1.0, 1.0));
+            }
+            let gain = envelope[i] * velocity_gain;
+            output_left[i] += raw_left[i] * gain * left_gain; // Additive mixing
+            output_right[i] += raw_right[i] * gain * right_gain;
+        }
+        self.buffers = buffers;
+    }
+
+    /// Reserves the buffers and per-operator state the voice renders with, so rendering
+    /// blocks of up to `max_block_size` samples with these settings never allocates.
+    pub fn prepare(
+        &mut self,
+        algorithm: &Algorithm,
+        operators: &[Operator],
+        params: &VoiceParams,
+        max_block_size: usize,
+    ) {
+        let operator_count = operators.len();
+        self.operator_states
+            .reserve(operator_count.saturating_sub(self.operator_states.len()));
+        self.operator_mods
+            .resize_with(operator_count, OperatorModulation::default);
+        for mods in self.operator_mods.iter_mut() {
+            for buffer in [
+                &mut mods.frequency,
+                &mut mods.gain,
+                &mut mods.index,
+                &mut mods.pan,
+            ] {
+                reserve_buffer(buffer, max_block_size);
+            }
+        }
+        self.lfos
+            .reserve(params.lfos.len().saturating_sub(self.lfos.len()));
+        self.lfo_values.resize_with(params.lfos.len(), Vec::new);
+        for values in self.lfo_values.iter_mut() {
+            reserve_buffer(values, max_block_size);
         }
 
-        // --- Update State & Increment Counter ---
+        let buffers = &mut self.buffers;
+        let [raw_left, raw_right] = &mut buffers.raw;
+        let [fade_left, fade_right] = &mut self.fade_buffers;
+        for buffer in [
+            &mut buffers.frequencies,
+            &mut buffers.envelope,
+            &mut buffers.filter_envelope,
+            raw_left,
+            raw_right,
+            &mut buffers.voice_mods.cutoff,
+            &mut buffers.voice_mods.pan,
+            &mut buffers.mod_buffers.pitch,
+            &mut buffers.mod_buffers.amp,
+            &mut buffers.mod_buffers.index,
+            &mut buffers.mod_buffers.amounts,
+            fade_left,
+            fade_right,
+        ] {
+            reserve_buffer(buffer, max_block_size);
+        }
+        buffers.algorithm.reserve(algorithm, max_block_size);
+    }
 
-        // Check if the envelope has finished its release phase *after* processing.
-        if !self.active && self.envelope.is_finished() {
-            // The voice was releasing and the envelope just finished.
-            // It's now truly inactive. No state change needed here, is_finished() handles it.
-            println!(
-                "Envelope finished release for note {}, voice fully inactive",
-                self.note_number
-            );
+    /// Reseeds the noise generators of every operator on this voice.
+    /// Each operator gets its own stream derived from `seed`.
+    pub fn seed_noise(&mut self, seed: u64) {
+        self.noise_seed = seed;
+        for (i, state) in self.operator_states.iter_mut().enumerate() {
+            state.noise.seed(derive_seed(seed, i as u64));
         }
+        self.random.seed(derive_seed(seed, RANDOM_SEED_STREAM));
+    }
 
-        // Increment the sample counter *after* processing this buffer.
-        // Only increment if the voice was considered active OR was releasing during this buffer.
-        self.samples_elapsed_since_trigger += buffer_len as u64;
+    /// Resizes the per-operator state to match `operators` and copies their envelope settings.
+    /// States created while the voice is sounding are triggered so they join the current note.
+    /// `velocity` is the curved note velocity used for each operator's velocity sensitivity.
+    fn sync_operator_states(&mut self, operators: &[Operator], velocity: f32) {
+        let previous_len = self.operator_states.len();
+        self.operator_states
+            .resize_with(operators.len(), OperatorState::new);
+        for (i, state) in self
+            .operator_states
+            .iter_mut()
+            .enumerate()
+            .skip(previous_len)
+        {
+            state.noise.seed(derive_seed(self.noise_seed, i as u64));
+            if self.active {
+                state.trigger();
+            }
+        }
+        for (state, operator) in self.operator_states.iter_mut().zip(operators) {
+            state.sync(operator);
+            state.velocity_scale = velocity_scale(operator.velocity_sensitivity, velocity);
+        }
+    }
+
+    /// Runs the per-voice LFOs and collects the output of every LFO, per-voice or global,
+    /// into `lfo_values` for this buffer. Global LFOs missing from `shared` read as silent.
+    fn update_lfos(
+        &mut self,
+        params: &VoiceParams,
+        shared: &SharedModulation,
+        offset: usize,
+        buffer_len: usize,
+        sample_rate: f32,
+    ) {
+        let previous_len = self.lfos.len();
+        self.lfos.resize_with(params.lfos.len(), Lfo::default);
+        for (i, lfo) in self.lfos.iter_mut().enumerate().skip(previous_len) {
+            *lfo = Lfo::new(derive_seed(self.noise_seed, LFO_SEED_STREAM + i as u64));
+        }
+        if std::mem::take(&mut self.lfo_trigger_pending) {
+            for (lfo, settings) in self.lfos.iter_mut().zip(&params.lfos) {
+                lfo.trigger(settings);
+            }
+        }
+
+        self.lfo_values.resize_with(params.lfos.len(), Vec::new);
+        for (i, ((lfo, settings), values)) in self
+            .lfos
+            .iter_mut()
+            .zip(&params.lfos)
+            .zip(self.lfo_values.iter_mut())
+            .enumerate()
+        {
+            values.clear();
+            values.resize(buffer_len, 0.0);
+            if settings.per_voice {
+                lfo.process(settings, values, shared.tempo, sample_rate);
+            } else if let Some(global) = shared
+                .lfos
+                .get(i)
+                .and_then(|v| v.get(offset..offset + buffer_len))
+            {
+                values.copy_from_slice(global);
+            }
+        }
     }
 
     /// Checks if the voice is completely finished (inactive and envelope has finished).
     pub fn is_finished(&self) -> bool {
         // A voice is finished if it's not marked active (i.e., released)
-        // AND its envelope has reached the idle state (value is effectively zero).
-        !self.active && self.envelope.is_finished()
+        // AND its envelope has reached the idle state (value is effectively zero)
+        // AND it has no stolen note waiting to start.
+        !self.active && self.envelope.is_finished() && self.pending_note.is_none()
     }
 }
 impl Default for Voice {
@@ -137,9 +702,212 @@ impl Default for Voice {
             active: false,
             note_number: 0,
             note_frequency: 0.0, // Will be set on activation
+            velocity: 0,
+            note_id: 0,
             note_source: None,
+            unison: UnisonOffset::default(),
+            sustained: false,
+            sostenuto: false,
             envelope: EnvelopeGenerator::new(),
-            samples_elapsed_since_trigger: 0,
+            operator_states: Vec::new(), // Sized on first use to match the engine's operators
+            noise_seed: 0,
+            filter: [Filter::new(), Filter::new()],
+            ladder: [LadderFilter::new(), LadderFilter::new()],
+            filter_envelope: EnvelopeGenerator::new(),
+            pending_note: None,
+            fade_remaining: 0,
+            fade_length: 0,
+            glide: Glide::new(),
+            lfos: Vec::new(),
+            lfo_trigger_pending: false,
+            lfo_values: Vec::new(),
+            operator_mods: Vec::new(),
+            random: NoiseGenerator::new(derive_seed(0, RANDOM_SEED_STREAM)),
+            note_random: 0.0,
+            buffers: RenderBuffers::default(), // Sized by `prepare`
+            fade_buffers: [Vec::new(), Vec::new()],
         }
     }
 }
+
+/// Noise stream offset for LFO seeds, keeping them apart from the operator streams.
+const LFO_SEED_STREAM: u64 = 1 << 32;
+/// Noise stream for the per-note random mod source.
+const RANDOM_SEED_STREAM: u64 = 1 << 33;
+
+/// Samples per step when the voice filter's cutoff is modulated.
+const FILTER_MOD_BLOCK: usize = 32;
+
+/// Scratch buffers for `Voice::render`, kept between buffers so rendering doesn't allocate.
+#[derive(Default)]
+struct RenderBuffers {
+    frequencies: Vec<f32>,     // Per-sample pitch of the voice
+    envelope: Vec<f32>,        // Main envelope level
+    filter_envelope: Vec<f32>, // Filter envelope level, then the ladder cutoff
+    raw: [Vec<f32>; 2],        // Algorithm output before the filters and envelope
+    voice_mods: VoiceMods,
+    mod_buffers: ModBuffers,
+    algorithm: AlgorithmBuffers,
+}
+
+/// Voice-wide modulation from the mod matrix for one buffer; empty means unmodulated.
+#[derive(Default)]
+struct VoiceMods {
+    cutoff: Vec<f32>, // Filter cutoff offset in octaves
+    pan: Vec<f32>,    // Offset added to the unison pan
+}
+
+/// Working space for `update_operator_mods`.
+#[derive(Default)]
+struct ModBuffers {
+    pitch: Vec<f32>,   // Summed LFO pitch in cents
+    amp: Vec<f32>,     // Summed LFO level dip
+    index: Vec<f32>,   // Summed LFO index swing
+    amounts: Vec<f32>, // One mod matrix slot's output
+}
+
+/// Combines the LFOs' fixed routings and the mod matrix into per-operator pitch, level,
+/// modulation-index and pan buffers in `operator_mods`. LFO routings are scaled by each
+/// operator's sensitivities; matrix slots apply as they are. The voice-wide targets go to
+/// `voice_mods`.
+fn update_operator_mods(
+    operator_mods: &mut Vec<OperatorModulation>,
+    voice_mods: &mut VoiceMods,
+    buffers: &mut ModBuffers,
+    operators: &[Operator],
+    params: &VoiceParams,
+    sources: &ModSourceValues,
+    buffer_len: usize,
+) {
+    // Summed LFO output per destination: pitch in cents, level dip (0.0-1.0), index swing
+    let ModBuffers {
+        pitch,
+        amp,
+        index,
+        amounts,
+    } = buffers;
+    fill_buffer(pitch, buffer_len, 0.0);
+    fill_buffer(amp, buffer_len, 0.0);
+    fill_buffer(index, buffer_len, 0.0);
+    let (mut has_pitch, mut has_amp, mut has_index) = (false, false, false);
+    for (settings, values) in params.lfos.iter().zip(sources.lfos) {
+        for (j, value) in values.iter().enumerate() {
+            pitch[j] += value * settings.pitch_depth;
+            // Tremolo only ever lowers the level, like DX7 amplitude modulation
+            amp[j] += (1.0 - value) * 0.5 * settings.amp_depth;
+            index[j] += value * settings.index_depth;
+        }
+        has_pitch |= settings.pitch_depth != 0.0;
+        has_amp |= settings.amp_depth != 0.0;
+        has_index |= settings.index_depth != 0.0;
+    }
+
+    operator_mods.resize_with(operators.len(), OperatorModulation::default);
+    for (mods, operator) in operator_mods.iter_mut().zip(operators) {
+        mods.frequency.clear();
+        mods.gain.clear();
+        mods.index.clear();
+        mods.pan.clear();
+        let sensitivity = operator.pitch_mod_sensitivity;
+        if has_pitch && sensitivity != 0.0 {
+            mods.frequency.extend(
+                pitch
+                    .iter()
+                    .map(|cents| (cents * sensitivity / 1200.0).exp2()),
+            );
+        }
+        let sensitivity = operator.amp_mod_sensitivity;
+        if has_amp && sensitivity != 0.0 {
+            mods.gain
+                .extend(amp.iter().map(|dip| (1.0 - dip * sensitivity).max(0.0)));
+        }
+        let sensitivity = operator.index_mod_sensitivity;
+        if has_index && sensitivity != 0.0 {
+            mods.index.extend(
+                index
+                    .iter()
+                    .map(|swing| (1.0 + swing * sensitivity).max(0.0)),
+            );
+        }
+    }
+
+    voice_mods.cutoff.clear();
+    voice_mods.pan.clear();
+    fill_buffer(amounts, buffer_len, 0.0);
+    for slot in params.mod_matrix.active_slots() {
+        sources.fill(slot.source, amounts);
+        for amount in amounts.iter_mut() {
+            *amount = slot.curve.apply(*amount) * slot.amount;
+        }
+        match slot.destination {
+            ModDestination::OperatorRatio(op) => {
+                let Some(operator) = operators.get(op) else {
+                    continue;
+                };
+                // Fixed-frequency operators have no ratio to move
+                let ratio = operator.frequency_ratio;
+                if operator.fixed_frequency.is_none() && ratio != 0.0 {
+                    scale_mod(&mut operator_mods[op].frequency, amounts, |amount| {
+                        ((ratio + amount) / ratio).max(0.0)
+                    });
+                }
+            }
+            ModDestination::OperatorGain(op) => {
+                if let Some(mods) = operator_mods.get_mut(op) {
+                    scale_mod(&mut mods.gain, amounts, |amount| (1.0 + amount).max(0.0));
+                }
+            }
+            ModDestination::OperatorModIndex(op) => {
+                if let Some(mods) = operator_mods.get_mut(op) {
+                    scale_mod(&mut mods.index, amounts, |amount| (1.0 + amount).max(0.0));
+                }
+            }
+            ModDestination::OperatorPan(op) => {
+                if let Some(mods) = operator_mods.get_mut(op) {
+                    offset_mod(&mut mods.pan, amounts);
+                }
+            }
+            ModDestination::FilterCutoff => offset_mod(&mut voice_mods.cutoff, amounts),
+            ModDestination::Pan => offset_mod(&mut voice_mods.pan, amounts),
+            // Applied by the engine to the whole mix
+            ModDestination::MasterVolume => {}
+        }
+    }
+}
+
+/// Multiplies a modulation buffer by `factor(amount)` per sample, treating empty as all 1.0.
+fn scale_mod(target: &mut Vec<f32>, amounts: &[f32], factor: impl Fn(f32) -> f32) {
+    if target.is_empty() {
+        target.resize(amounts.len(), 1.0);
+    }
+    for (value, &amount) in target.iter_mut().zip(amounts) {
+        *value *= factor(amount);
+    }
+}
+
+/// Adds `amounts` to a modulation buffer, treating empty as all 0.0.
+fn offset_mod(target: &mut Vec<f32>, amounts: &[f32]) {
+    if target.is_empty() {
+        target.resize(amounts.len(), 0.0);
+    }
+    for (value, amount) in target.iter_mut().zip(amounts) {
+        *value += amount;
+    }
+}
+
+/// Sets `buffer` to `len` copies of `value`, reusing its allocation.
+fn fill_buffer(buffer: &mut Vec<f32>, len: usize, value: f32) {
+    buffer.clear();
+    buffer.resize(len, value);
+}
+
+/// Makes sure `buffer` can hold `len` samples without reallocating.
+fn reserve_buffer(buffer: &mut Vec<f32>, len: usize) {
+    buffer.reserve(len.saturating_sub(buffer.len()));
+}
+
+/// Level for a curved velocity (0.0-1.0) at the given sensitivity (0.0-1.0).
+/// At zero sensitivity the level is always 1.0; at full sensitivity it equals the velocity.
+fn velocity_scale(sensitivity: f32, velocity: f32) -> f32 {
+    1.0 - sensitivity.clamp(0.0, 1.0) * (1.0 - velocity)
+}
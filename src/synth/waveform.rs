@@ -1,4 +1,6 @@
-use rand::Rng;
+use super::noise::{NoiseColor, NoiseGenerator};
+use super::wavetable::Wavetable;
+use std::sync::Arc;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Waveform {
@@ -7,28 +9,106 @@ pub enum Waveform {
     Sawtooth,
     Triangle,
     Noise,
+    Wavetable, // Plays `WaveformGenerator::wavetable`, falling back to a sine if none is loaded
+    // Yamaha OPL3/TX81Z-style operator waves (Sine and Square complete the set of eight)
+    HalfSine,        // Positive half of the sine, silent for the negative half
+    AbsSine,         // Rectified sine: both halves positive
+    QuarterSine,     // Rising quarter of the sine in each half, silent otherwise
+    AlternatingSine, // A full sine at double speed in the first half, silent in the second
+    CamelSine,       // Rectified double-speed sine in the first half, silent in the second
+    DerivedSquare,   // Square whose halves decay exponentially towards zero
+}
+
+impl Waveform {
+    /// The eight OPL3 waveforms in register order (0 = Sine ... 7 = DerivedSquare).
+    pub const OPL: [Waveform; 8] = [
+        Waveform::Sine,
+        Waveform::HalfSine,
+        Waveform::AbsSine,
+        Waveform::QuarterSine,
+        Waveform::AlternatingSine,
+        Waveform::CamelSine,
+        Waveform::Square,
+        Waveform::DerivedSquare,
+    ];
 }
 
 #[derive(Debug, Clone)] // Added Debug and Clone
 pub struct WaveformGenerator {
     pub waveform: Waveform, // Made public for inspection/logging if needed
+    pub band_limited: bool, // Use PolyBLEP/BLAMP versions of Square, Sawtooth and Triangle
+    pub wavetable: Option<Arc<Wavetable>>, // Table played by Waveform::Wavetable
+    pub wavetable_position: f32, // Frame position in [0, 1], morphing between frames
+    pub noise_color: NoiseColor, // Spectrum of Waveform::Noise
 }
 
 impl WaveformGenerator {
     pub fn new(waveform: Waveform) -> Self {
-        Self { waveform }
+        Self {
+            waveform,
+            band_limited: true,
+            wavetable: None,
+            wavetable_position: 0.0,
+            noise_color: NoiseColor::White,
+        }
     }
+    /// Fills `output` starting at `phase` (radians, wrapped to [0, 2π)) and returns the phase
+    /// to continue from on the next buffer. `phase_increments` holds the phase step for each
+    /// sample, so the pitch can move within a buffer. `modulation` is added to the phase per sample.
+    /// `noise` is the caller's noise source, used by Waveform::Noise.
     pub fn generate(
         &self,
-        frequency: f32,
-        sample_rate: f32,
-        // TODO: externally should wrap phase_offset if it grows large -- phase_offset = phase_offset % (2.0 * std::f32::consts::PI)
-        phase_offset: f32,
+        phase: f32,
+        phase_increments: &[f32],
         output: &mut [f32],
         modulation: &[f32],
-    ) {
+        noise: &mut NoiseGenerator,
+    ) -> f32 {
+        if self.waveform == Waveform::Noise {
+            let mut current_phase = phase;
+            for (sample, phase_increment) in output.iter_mut().zip(phase_increments) {
+                *sample = noise.next(self.noise_color);
+                // Keep the phase running so switching waveforms stays continuous
+                current_phase = wrap_phase(current_phase + phase_increment);
+            }
+            return current_phase;
+        }
+
+        if let (Waveform::Wavetable, Some(wavetable)) = (self.waveform, &self.wavetable) {
+            let mut current_phase = phase;
+            for ((sample, modulation), &phase_increment) in
+                output.iter_mut().zip(modulation).zip(phase_increments)
+            {
+                let level = wavetable.mip_level((phase_increment / TWO_PI).abs());
+                let t = wrap_unit((current_phase + modulation) / TWO_PI);
+                *sample = wavetable.sample(t, self.wavetable_position, level);
+                current_phase = wrap_phase(current_phase + phase_increment);
+            }
+            return current_phase;
+        }
+
+        // Band-limited shapes work on the normalized phase and need the per-sample phase step
+        let band_limited_wave: Option<fn(f32, f32) -> f32> = match self.waveform {
+            Waveform::Square if self.band_limited => Some(band_limited_square),
+            Waveform::Sawtooth if self.band_limited => Some(band_limited_sawtooth),
+            Waveform::Triangle if self.band_limited => Some(band_limited_triangle),
+            _ => None,
+        };
+        if let Some(generate_wave) = band_limited_wave {
+            let mut current_phase = phase;
+            for ((sample, modulation), &phase_increment) in
+                output.iter_mut().zip(modulation).zip(phase_increments)
+            {
+                let dt = (phase_increment / TWO_PI).abs().min(0.5);
+                let t = wrap_unit((current_phase + modulation) / TWO_PI);
+                *sample = generate_wave(t, dt);
+                current_phase = wrap_phase(current_phase + phase_increment);
+            }
+            return current_phase;
+        }
+
         // TODO: this implementation relies on slightly more expensive transcendental functions such as asin()
-        // in the future may want to look into modulo arithmetic and other optimizations (PolyBLEP etc.)
+        // in the future may want to look into modulo arithmetic and other optimizations
         let generate_wave = match self.waveform {
             Waveform::Sine => |phase: f32| phase.sin(),
             Waveform::Square => |phase: f32| if phase.sin() >= 0.0 { 1.0 } else { -1.0 },
@@ -37,21 +117,63 @@ impl WaveformGenerator {
                 2.0 * (cycles - (cycles + 0.5).floor())
             },
             Waveform::Triangle => |phase: f32| (2.0 / std::f32::consts::PI) * (phase.sin()).asin(),
-            Waveform::Noise => |_phase: f32| rand::thread_rng().gen_range(-1.0..1.0),
+            Waveform::Noise => |_phase: f32| 0.0, // Generated above
+            Waveform::Wavetable => |phase: f32| phase.sin(), // No table loaded
+            Waveform::HalfSine => |phase: f32| phase.sin().max(0.0),
+            Waveform::AbsSine => |phase: f32| phase.sin().abs(),
+            Waveform::QuarterSine => |phase: f32| {
+                if wrap_unit(phase / TWO_PI) % 0.5 < 0.25 {
+                    phase.sin().abs()
+                } else {
+                    0.0
+                }
+            },
+            Waveform::AlternatingSine => |phase: f32| {
+                if wrap_unit(phase / TWO_PI) < 0.5 {
+                    (2.0 * phase).sin()
+                } else {
+                    0.0
+                }
+            },
+            Waveform::CamelSine => |phase: f32| {
+                if wrap_unit(phase / TWO_PI) < 0.5 {
+                    (2.0 * phase).sin().abs()
+                } else {
+                    0.0
+                }
+            },
+            Waveform::DerivedSquare => |phase: f32| {
+                let t = wrap_unit(phase / TWO_PI);
+                if t < 0.5 {
+                    (-16.0 * t).exp2()
+                } else {
+                    -(-16.0 * (1.0 - t)).exp2()
+                }
+            },
         };
 
-        let phase_increment = 2.0 * std::f32::consts::PI * frequency / sample_rate;
-
-        for (i, sample) in output.iter_mut().enumerate() {
-            let current_phase = phase_offset + phase_increment * (i as f32);
-            *sample = generate_wave(current_phase + modulation[i]);
+        let mut current_phase = phase;
+        for ((sample, modulation), phase_increment) in
+            output.iter_mut().zip(modulation).zip(phase_increments)
+        {
+            *sample = generate_wave(current_phase + modulation);
+            current_phase = wrap_phase(current_phase + phase_increment);
         }
+        current_phase
     }
+    // Cycles through the OPL waves in register order, then the classic shapes.
+    // Wavetable needs a loaded table, so it is left out of the cycle
     pub fn get_next_waveform(&mut self) {
         self.waveform = match self.waveform {
-            Waveform::Noise => Waveform::Sine,
-            Waveform::Sine => Waveform::Square,
-            Waveform::Square => Waveform::Sawtooth,
+            Waveform::Noise | Waveform::Wavetable => Waveform::Sine,
+            Waveform::Sine => Waveform::HalfSine,
+            Waveform::HalfSine => Waveform::AbsSine,
+            Waveform::AbsSine => Waveform::QuarterSine,
+            Waveform::QuarterSine => Waveform::AlternatingSine,
+            Waveform::AlternatingSine => Waveform::CamelSine,
+            Waveform::CamelSine => Waveform::Square,
+            Waveform::Square => Waveform::DerivedSquare,
+            Waveform::DerivedSquare => Waveform::Sawtooth,
             Waveform::Sawtooth => Waveform::Triangle,
             Waveform::Triangle => Waveform::Noise,
         };
@@ -59,13 +181,106 @@ impl WaveformGenerator {
     pub fn get_previous_waveform(&mut self) {
         self.waveform = match self.waveform {
             Waveform::Noise => Waveform::Triangle,
-            Waveform::Sine => Waveform::Noise,
-            Waveform::Square => Waveform::Sine,
-            Waveform::Sawtooth => Waveform::Square,
+            Waveform::Sine | Waveform::Wavetable => Waveform::Noise,
+            Waveform::HalfSine => Waveform::Sine,
+            Waveform::AbsSine => Waveform::HalfSine,
+            Waveform::QuarterSine => Waveform::AbsSine,
+            Waveform::AlternatingSine => Waveform::QuarterSine,
+            Waveform::CamelSine => Waveform::AlternatingSine,
+            Waveform::Square => Waveform::CamelSine,
+            Waveform::DerivedSquare => Waveform::Square,
+            Waveform::Sawtooth => Waveform::DerivedSquare,
             Waveform::Triangle => Waveform::Sawtooth,
         };
     }
     pub fn set_waveform(&mut self, waveform: Waveform) {
         self.waveform = waveform;
     }
+    /// Loads a wavetable and switches to the Wavetable waveform.
+    pub fn set_wavetable(&mut self, wavetable: Arc<Wavetable>) {
+        self.wavetable = Some(wavetable);
+        self.waveform = Waveform::Wavetable;
+    }
+}
+
+const TWO_PI: f32 = 2.0 * std::f32::consts::PI;
+
+/// Wraps a phase in radians into [0, 2π).
+pub fn wrap_phase(phase: f32) -> f32 {
+    if (0.0..TWO_PI).contains(&phase) {
+        phase
+    } else if (TWO_PI..2.0 * TWO_PI).contains(&phase) {
+        phase - TWO_PI
+    } else {
+        phase.rem_euclid(TWO_PI)
+    }
+}
+
+// --- Band-limited waveforms ---
+// `t` is the normalized phase in [0, 1) and `dt` the phase step per sample in cycles.
+// Each shape matches its naive closure above, with the discontinuities smoothed by
+// polynomial residuals spanning one sample either side.
+
+fn band_limited_square(t: f32, dt: f32) -> f32 {
+    let naive = if t < 0.5 { 1.0 } else { -1.0 };
+    naive + poly_blep(t, dt) - poly_blep(wrap_unit(t + 0.5), dt)
+}
+
+fn band_limited_sawtooth(t: f32, dt: f32) -> f32 {
+    // The naive sawtooth resets half way through the cycle
+    let t = wrap_unit(t + 0.5);
+    2.0 * t - 1.0 - poly_blep(t, dt)
+}
+
+fn band_limited_triangle(t: f32, dt: f32) -> f32 {
+    let naive = if t < 0.25 {
+        4.0 * t
+    } else if t < 0.75 {
+        2.0 - 4.0 * t
+    } else {
+        4.0 * t - 4.0
+    };
+    // The slope changes by -8 per cycle at the peak and by +8 at the trough
+    naive + 8.0 * dt * (poly_blamp(wrap_unit(t + 0.25), dt) - poly_blamp(wrap_unit(t - 0.25), dt))
+}
+
+/// PolyBLEP residual for a step of height 2 at t = 0.
+fn poly_blep(t: f32, dt: f32) -> f32 {
+    if dt <= 0.0 {
+        0.0
+    } else if t < dt {
+        let x = t / dt;
+        2.0 * x - x * x - 1.0
+    } else if t > 1.0 - dt {
+        let x = (t - 1.0) / dt;
+        x * x + 2.0 * x + 1.0
+    } else {
+        0.0
+    }
+}
+
+/// PolyBLAMP residual for a slope change of one per sample at t = 0.
+fn poly_blamp(t: f32, dt: f32) -> f32 {
+    if dt <= 0.0 {
+        0.0
+    } else if t < dt {
+        let x = 1.0 - t / dt;
+        x * x * x / 6.0
+    } else if t > 1.0 - dt {
+        let x = 1.0 + (t - 1.0) / dt;
+        x * x * x / 6.0
+    } else {
+        0.0
+    }
+}
+
+/// Wraps a normalized phase into [0, 1).
+fn wrap_unit(t: f32) -> f32 {
+    let wrapped = t - t.floor();
+    // Tiny negative inputs can round up to exactly 1.0
+    if wrapped < 1.0 {
+        wrapped
+    } else {
+        0.0
+    }
 }
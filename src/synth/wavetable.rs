@@ -0,0 +1,243 @@
+use crate::utils::wav::read_wav;
+use std::path::Path;
+
+/// Number of samples every frame is resampled to.
+pub const TABLE_SIZE: usize = 2048;
+
+/// A set of single-cycle frames, each stored as a chain of band-limited mipmaps.
+///
+/// Level 0 keeps every harmonic the table can hold; each following level halves the
+/// harmonic count, down to a pure fundamental. Playback picks the richest level whose
+/// highest harmonic stays below Nyquist, and crossfades between neighbouring frames.
+#[derive(Debug)]
+pub struct Wavetable {
+    // frames[frame][level][sample]
+    frames: Vec<Vec<Vec<f32>>>,
+    // Highest harmonic present in each mip level
+    level_harmonics: Vec<usize>,
+}
+
+impl Wavetable {
+    /// Builds a wavetable from one or more single-cycle frames of any length.
+    /// The frames are resampled to `TABLE_SIZE`, DC is removed and the table is
+    /// normalized to a peak of 1.0.
+    pub fn from_frames(frames: Vec<Vec<f32>>) -> Result<Self, String> {
+        if frames.is_empty() {
+            return Err("Wavetable needs at least one frame.".to_string());
+        }
+        if let Some(short) = frames.iter().find(|frame| frame.len() < 2) {
+            return Err(format!(
+                "Wavetable frames need at least 2 samples, got {}.",
+                short.len()
+            ));
+        }
+
+        let mut level_harmonics = Vec::new();
+        let mut harmonics = TABLE_SIZE / 2 - 1;
+        while harmonics >= 1 {
+            level_harmonics.push(harmonics);
+            harmonics /= 2;
+        }
+
+        let mut mipmapped: Vec<Vec<Vec<f32>>> = frames
+            .iter()
+            .map(|frame| build_mipmaps(&resample_cycle(frame, TABLE_SIZE), &level_harmonics))
+            .collect();
+
+        let peak = mipmapped
+            .iter()
+            .flat_map(|levels| levels[0].iter())
+            .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+        if peak > 0.0 {
+            for sample in mipmapped.iter_mut().flatten().flatten() {
+                *sample /= peak;
+            }
+        }
+
+        Ok(Self {
+            frames: mipmapped,
+            level_harmonics,
+        })
+    }
+
+    /// Splits raw samples into consecutive frames of `frame_size` samples.
+    /// Trailing samples that don't fill a whole frame are ignored.
+    pub fn from_samples(samples: &[f32], frame_size: usize) -> Result<Self, String> {
+        if frame_size == 0 || samples.len() < frame_size {
+            return Err(format!(
+                "Need at least one frame of {} samples, got {} samples.",
+                frame_size,
+                samples.len()
+            ));
+        }
+        Self::from_frames(
+            samples
+                .chunks_exact(frame_size)
+                .map(|frame| frame.to_vec())
+                .collect(),
+        )
+    }
+
+    /// Loads a WAV file (mixed down to mono). With `frame_size` of `None` the whole file is
+    /// treated as one cycle; otherwise it is split into frames of that many samples.
+    pub fn from_wav_file<P: AsRef<Path>>(
+        path: P,
+        frame_size: Option<usize>,
+    ) -> Result<Self, String> {
+        let samples = read_wav(path)?.to_mono();
+        let frame_size = frame_size.unwrap_or(samples.len());
+        Self::from_samples(&samples, frame_size)
+    }
+
+    /// Loads a headerless file of little-endian 32-bit floats split into `frame_size` frames.
+    pub fn from_raw_f32_file<P: AsRef<Path>>(path: P, frame_size: usize) -> Result<Self, String> {
+        let bytes = std::fs::read(path.as_ref())
+            .map_err(|e| format!("Failed to read {}: {}", path.as_ref().display(), e))?;
+        if bytes.len() % 4 != 0 {
+            return Err("Raw f32 wavetable length is not a multiple of 4 bytes.".to_string());
+        }
+        let samples: Vec<f32> = bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        Self::from_samples(&samples, frame_size)
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Picks the richest mip level that doesn't alias at `dt` (phase step per sample in cycles).
+    pub fn mip_level(&self, dt: f32) -> usize {
+        let max_harmonic = if dt > 0.0 { 0.5 / dt } else { f32::MAX };
+        self.level_harmonics
+            .iter()
+            .position(|&harmonics| harmonics as f32 <= max_harmonic)
+            .unwrap_or(self.level_harmonics.len() - 1)
+    }
+
+    /// Reads the table at normalized phase `t` in [0, 1) and frame `position` in [0, 1],
+    /// interpolating linearly within and between frames.
+    pub fn sample(&self, t: f32, position: f32, level: usize) -> f32 {
+        let frame_pos = position.clamp(0.0, 1.0) * (self.frames.len() - 1) as f32;
+        let frame_index = frame_pos.floor() as usize;
+        let frame_fraction = frame_pos - frame_index as f32;
+
+        let current = read_table(&self.frames[frame_index][level], t);
+        if frame_fraction > 0.0 && frame_index + 1 < self.frames.len() {
+            let next = read_table(&self.frames[frame_index + 1][level], t);
+            current + (next - current) * frame_fraction
+        } else {
+            current
+        }
+    }
+}
+
+fn read_table(table: &[f32], t: f32) -> f32 {
+    let position = t * table.len() as f32;
+    let index = position as usize % table.len();
+    let fraction = position - position.floor();
+    let a = table[index];
+    let b = table[(index + 1) % table.len()];
+    a + (b - a) * fraction
+}
+
+/// Resamples one periodic cycle to `size` samples (a power of two).
+/// Longer cycles are shrunk through the spectrum, dropping the harmonics `size` samples
+/// can't hold instead of letting them fold back into the table.
+fn resample_cycle(cycle: &[f32], size: usize) -> Vec<f32> {
+    if cycle.len() <= size {
+        return interpolate_cycle(cycle, size);
+    }
+
+    // Stretch to a power of two first so the FFT can take it; stretching doesn't alias
+    let padded = interpolate_cycle(cycle, cycle.len().next_power_of_two());
+    let n = padded.len();
+    let mut spectrum: Vec<(f32, f32)> = padded.iter().map(|&x| (x, 0.0)).collect();
+    fft(&mut spectrum, false);
+
+    // Bin k of a buffer holding exactly one cycle is harmonic k
+    let mut truncated = vec![(0.0, 0.0); size];
+    truncated[0] = spectrum[0];
+    for harmonic in 1..size / 2 {
+        truncated[harmonic] = spectrum[harmonic];
+        truncated[size - harmonic] = spectrum[n - harmonic];
+    }
+    fft(&mut truncated, true);
+    truncated.iter().map(|&(re, _)| re / n as f32).collect()
+}
+
+/// Linearly resamples one periodic cycle to `size` samples.
+fn interpolate_cycle(cycle: &[f32], size: usize) -> Vec<f32> {
+    if cycle.len() == size {
+        return cycle.to_vec();
+    }
+    (0..size)
+        .map(|i| {
+            let position = i as f32 * cycle.len() as f32 / size as f32;
+            let index = position as usize;
+            let fraction = position - index as f32;
+            let a = cycle[index % cycle.len()];
+            let b = cycle[(index + 1) % cycle.len()];
+            a + (b - a) * fraction
+        })
+        .collect()
+}
+
+/// Builds one table per entry in `level_harmonics`, each keeping harmonics up to that limit.
+fn build_mipmaps(table: &[f32], level_harmonics: &[usize]) -> Vec<Vec<f32>> {
+    let size = table.len();
+    let mut spectrum: Vec<(f32, f32)> = table.iter().map(|&x| (x, 0.0)).collect();
+    fft(&mut spectrum, false);
+
+    level_harmonics
+        .iter()
+        .map(|&max_harmonic| {
+            let mut level: Vec<(f32, f32)> = vec![(0.0, 0.0); size];
+            // Bin 0 (DC) stays empty
+            for harmonic in 1..=max_harmonic.min(size / 2 - 1) {
+                level[harmonic] = spectrum[harmonic];
+                level[size - harmonic] = spectrum[size - harmonic];
+            }
+            fft(&mut level, true);
+            level.iter().map(|&(re, _)| re / size as f32).collect()
+        })
+        .collect()
+}
+
+/// In-place iterative radix-2 FFT over (re, im) pairs. The length must be a power of two.
+/// The inverse transform is left unscaled.
+fn fft(data: &mut [(f32, f32)], inverse: bool) {
+    let n = data.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        let angle = sign * 2.0 * std::f64::consts::PI / len as f64;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (w_im, w_re) = (angle * k as f64).sin_cos();
+                let (w_re, w_im) = (w_re as f32, w_im as f32);
+                let (a_re, a_im) = data[start + k];
+                let (b_re, b_im) = data[start + k + len / 2];
+                let t_re = b_re * w_re - b_im * w_im;
+                let t_im = b_re * w_im + b_im * w_re;
+                data[start + k] = (a_re + t_re, a_im + t_im);
+                data[start + k + len / 2] = (a_re - t_re, a_im - t_im);
+            }
+        }
+        len <<= 1;
+    }
+}
@@ -0,0 +1,267 @@
+use std::fs;
+use std::path::Path;
+
+/// How the delta times of a MIDI file are measured.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Division {
+    TicksPerQuarter(u16), // Musical time; the tempo map turns it into seconds
+    Smpte {
+        frames_per_second: u8, // 24, 25, 29 (29.97 drop-frame) or 30
+        ticks_per_frame: u8,
+    },
+}
+
+/// The events of a MIDI file the synth can use. Program changes, polyphonic aftertouch,
+/// SysEx and other meta events are skipped while parsing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MidiMessage {
+    NoteOn {
+        channel: u8,
+        note: u8,
+        velocity: u8, // 1-127; note-ons with velocity 0 are read as NoteOff
+    },
+    NoteOff {
+        channel: u8,
+        note: u8,
+    },
+    ControlChange {
+        channel: u8,
+        controller: u8,
+        value: u8,
+    },
+    PitchBend {
+        channel: u8,
+        value: i16, // -8192 to 8191, 0 = centre
+    },
+    ChannelPressure {
+        channel: u8,
+        value: u8,
+    },
+    Tempo(u32), // Microseconds per quarter note
+}
+
+/// A message at an absolute position in ticks from the start of its track.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MidiEvent {
+    pub tick: u64,
+    pub message: MidiMessage,
+}
+
+/// Decoded contents of a Standard MIDI File (type 0 or 1).
+#[derive(Clone, Debug)]
+pub struct MidiFile {
+    pub format: u16,
+    pub division: Division,
+    pub tracks: Vec<Vec<MidiEvent>>,
+}
+
+/// Reads a Standard MIDI File of type 0 (one track) or 1 (simultaneous tracks).
+pub fn read_midi_file<P: AsRef<Path>>(path: P) -> Result<MidiFile, String> {
+    let bytes = fs::read(path.as_ref())
+        .map_err(|e| format!("Failed to read {}: {}", path.as_ref().display(), e))?;
+    parse_midi_file(&bytes)
+}
+
+/// Parses the bytes of a Standard MIDI File. See `read_midi_file`.
+pub fn parse_midi_file(bytes: &[u8]) -> Result<MidiFile, String> {
+    if bytes.len() < 14 || &bytes[0..4] != b"MThd" {
+        return Err("Not a Standard MIDI File.".to_string());
+    }
+    let header_len = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as usize;
+    if header_len < 6 {
+        return Err("MIDI header chunk is too short.".to_string());
+    }
+    let format = u16::from_be_bytes([bytes[8], bytes[9]]);
+    let track_count = u16::from_be_bytes([bytes[10], bytes[11]]) as usize;
+    let division = u16::from_be_bytes([bytes[12], bytes[13]]);
+    if format > 1 {
+        return Err(format!(
+            "MIDI file format {} is not supported (only 0 and 1).",
+            format
+        ));
+    }
+    let division = if division & 0x8000 != 0 {
+        // The high byte is the negative frame rate
+        let frames_per_second = ((division >> 8) as u8 as i8).unsigned_abs();
+        let ticks_per_frame = (division & 0xFF) as u8;
+        if frames_per_second == 0 || ticks_per_frame == 0 {
+            return Err("MIDI file has an invalid SMPTE division.".to_string());
+        }
+        Division::Smpte {
+            frames_per_second,
+            ticks_per_frame,
+        }
+    } else if division == 0 {
+        return Err("MIDI file has zero ticks per quarter note.".to_string());
+    } else {
+        Division::TicksPerQuarter(division)
+    };
+
+    let mut tracks = Vec::with_capacity(track_count);
+    let mut offset = 8 + header_len;
+    while offset + 8 <= bytes.len() && tracks.len() < track_count {
+        let id = &bytes[offset..offset + 4];
+        let size = u32::from_be_bytes(bytes[offset + 4..offset + 8].try_into().unwrap()) as usize;
+        let body_start = offset + 8;
+        let body_end = body_start
+            .checked_add(size)
+            .filter(|&end| end <= bytes.len())
+            .ok_or("MIDI track chunk runs past the end of the file.")?;
+        if id == b"MTrk" {
+            tracks.push(parse_track(&bytes[body_start..body_end])?);
+        }
+        // Unknown chunks are skipped, as the standard asks
+        offset = body_end;
+    }
+    if tracks.len() < track_count {
+        return Err(format!(
+            "MIDI file declares {} tracks but contains {}.",
+            track_count,
+            tracks.len()
+        ));
+    }
+
+    Ok(MidiFile {
+        format,
+        division,
+        tracks,
+    })
+}
+
+/// Reads the events of one MTrk chunk, resolving running status and delta times.
+fn parse_track(data: &[u8]) -> Result<Vec<MidiEvent>, String> {
+    let mut events = Vec::new();
+    let mut reader = Reader { data, offset: 0 };
+    let mut tick = 0u64;
+    let mut running_status: Option<u8> = None;
+
+    while reader.offset < data.len() {
+        tick += reader.variable_length()? as u64;
+        let mut status = reader.byte()?;
+        let first_data = if status < 0x80 {
+            // Running status: this byte is the first data byte of a repeated message
+            let data_byte = status;
+            status = running_status.ok_or("MIDI data byte without a status byte.")?;
+            Some(data_byte)
+        } else {
+            None
+        };
+
+        match status {
+            0xFF => {
+                let meta_type = reader.byte()?;
+                let length = reader.variable_length()? as usize;
+                let body = reader.take(length)?;
+                match meta_type {
+                    0x2F => break, // End of track
+                    0x51 if length == 3 => {
+                        let tempo = u32::from_be_bytes([0, body[0], body[1], body[2]]);
+                        events.push(MidiEvent {
+                            tick,
+                            message: MidiMessage::Tempo(tempo),
+                        });
+                    }
+                    _ => {}
+                }
+                // Meta events cancel running status
+                running_status = None;
+            }
+            0xF0 | 0xF7 => {
+                let length = reader.variable_length()? as usize;
+                reader.take(length)?;
+                running_status = None;
+            }
+            0x80..=0xEF => {
+                running_status = Some(status);
+                let channel = status & 0x0F;
+                let data1 = match first_data {
+                    Some(byte) => byte,
+                    None => reader.byte()?,
+                };
+                // Program change and channel pressure carry a single data byte
+                let data2 = match status & 0xF0 {
+                    0xC0 | 0xD0 => 0,
+                    _ => reader.byte()?,
+                };
+                let message = match status & 0xF0 {
+                    0x80 => Some(MidiMessage::NoteOff {
+                        channel,
+                        note: data1,
+                    }),
+                    0x90 if data2 == 0 => Some(MidiMessage::NoteOff {
+                        channel,
+                        note: data1,
+                    }),
+                    0x90 => Some(MidiMessage::NoteOn {
+                        channel,
+                        note: data1,
+                        velocity: data2,
+                    }),
+                    0xB0 => Some(MidiMessage::ControlChange {
+                        channel,
+                        controller: data1,
+                        value: data2,
+                    }),
+                    0xD0 => Some(MidiMessage::ChannelPressure {
+                        channel,
+                        value: data1,
+                    }),
+                    0xE0 => Some(MidiMessage::PitchBend {
+                        channel,
+                        value: (((data2 as i16) << 7) | data1 as i16) - 8192,
+                    }),
+                    _ => None, // Polyphonic aftertouch, program change
+                };
+                if let Some(message) = message {
+                    events.push(MidiEvent { tick, message });
+                }
+            }
+            _ => {
+                return Err(format!(
+                    "Unexpected MIDI status byte 0x{:02X} in track.",
+                    status
+                ))
+            }
+        }
+    }
+    Ok(events)
+}
+
+/// Cursor over the bytes of a track chunk.
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8, String> {
+        let byte = *self
+            .data
+            .get(self.offset)
+            .ok_or("MIDI track ends in the middle of an event.")?;
+        self.offset += 1;
+        Ok(byte)
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let body = self
+            .data
+            .get(self.offset..self.offset + length)
+            .ok_or("MIDI track ends in the middle of an event.")?;
+        self.offset += length;
+        Ok(body)
+    }
+
+    /// Reads a variable-length quantity (seven bits per byte, at most four bytes).
+    fn variable_length(&mut self) -> Result<u32, String> {
+        let mut value = 0u32;
+        for _ in 0..4 {
+            let byte = self.byte()?;
+            value = (value << 7) | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("MIDI variable-length value is longer than four bytes.".to_string())
+    }
+}
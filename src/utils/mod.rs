@@ -0,0 +1,6 @@
+pub mod midi_file;
+pub mod note_list;
+pub mod smoothing;
+pub mod spsc;
+pub mod triple_buffer;
+pub mod wav;
@@ -0,0 +1,78 @@
+use super::midi_file::{Division, MidiEvent, MidiFile, MidiMessage};
+use std::fs;
+use std::path::Path;
+
+/// Ticks of the MIDI file a note list becomes: 25 frames of 40 ticks, one tick per millisecond.
+const MILLISECONDS: Division = Division::Smpte {
+    frames_per_second: 25,
+    ticks_per_frame: 40,
+};
+
+/// Reads a note list file. See `parse_note_list` for the format.
+pub fn read_note_list<P: AsRef<Path>>(path: P) -> Result<MidiFile, String> {
+    let text = fs::read_to_string(path.as_ref())
+        .map_err(|e| format!("Failed to read {}: {}", path.as_ref().display(), e))?;
+    parse_note_list(&text)
+}
+
+/// Parses a plain text list of notes into a single-track MIDI file, so it can be played
+/// like one. Each line is `start duration note [velocity]`: times in seconds, the MIDI note
+/// number, and a velocity of 1-127 (100 if left out). `#` starts a comment.
+pub fn parse_note_list(text: &str) -> Result<MidiFile, String> {
+    let mut events = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |message: &str| format!("Note list line {}: {}", number + 1, message);
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let (start, duration, note, velocity) = match fields[..] {
+            [start, duration, note] => (start, duration, note, "100"),
+            [start, duration, note, velocity] => (start, duration, note, velocity),
+            _ => return Err(error("Expected `start duration note [velocity]`.")),
+        };
+        let start: f64 = start.parse().map_err(|_| error("Start is not a number."))?;
+        let duration: f64 = duration
+            .parse()
+            .map_err(|_| error("Duration is not a number."))?;
+        let note: u8 = note
+            .parse()
+            .ok()
+            .filter(|&note| note < 128)
+            .ok_or_else(|| error("Note must be a MIDI note number (0-127)."))?;
+        let velocity: u8 = velocity
+            .parse()
+            .ok()
+            .filter(|&velocity| (1..128).contains(&velocity))
+            .ok_or_else(|| error("Velocity must be 1-127."))?;
+        if start < 0.0 || duration < 0.0 {
+            return Err(error("Start and duration can't be negative."));
+        }
+
+        let to_ticks = |seconds: f64| (seconds * 1000.0).round() as u64;
+        events.push(MidiEvent {
+            tick: to_ticks(start),
+            message: MidiMessage::NoteOn {
+                channel: 0,
+                note,
+                velocity,
+            },
+        });
+        events.push(MidiEvent {
+            tick: to_ticks(start + duration),
+            message: MidiMessage::NoteOff { channel: 0, note },
+        });
+    }
+    // Note-offs go first on a shared tick, so a repeated note isn't cut short
+    events.sort_by_key(|event| {
+        let is_on = matches!(event.message, MidiMessage::NoteOn { .. });
+        (event.tick, is_on)
+    });
+
+    Ok(MidiFile {
+        format: 0,
+        division: MILLISECONDS,
+        tracks: vec![events],
+    })
+}
@@ -0,0 +1,57 @@
+/// One-pole smoother that moves a control value towards its target, removing the steps
+/// (zipper noise) of controllers that arrive once per buffer.
+#[derive(Clone, Debug)]
+pub struct Smoother {
+    current: f32,
+    target: f32,
+    time: f32, // Seconds to cover about 63% of a jump
+}
+
+impl Smoother {
+    pub fn new(value: f32, time: f32) -> Self {
+        Self {
+            current: value,
+            target: value,
+            time: time.max(0.0),
+        }
+    }
+
+    pub fn set_target(&mut self, target: f32) {
+        self.target = target;
+    }
+
+    /// Jumps straight to `value`, skipping the glide.
+    pub fn reset(&mut self, value: f32) {
+        self.current = value;
+        self.target = value;
+    }
+
+    pub fn value(&self) -> f32 {
+        self.current
+    }
+
+    /// Whether the value has reached its target.
+    pub fn is_settled(&self) -> bool {
+        self.current == self.target
+    }
+
+    /// Fills `output` with the smoothed value for each sample.
+    pub fn process(&mut self, output: &mut [f32], sample_rate: f32) {
+        if self.time <= 0.0 {
+            self.current = self.target;
+        }
+        if self.is_settled() {
+            output.fill(self.current);
+            return;
+        }
+        let coefficient = (-1.0 / (self.time * sample_rate)).exp();
+        for sample in output.iter_mut() {
+            self.current = self.target + (self.current - self.target) * coefficient;
+            *sample = self.current;
+        }
+        // Snap once the remaining distance is inaudible, so the smoother can report settled
+        if (self.current - self.target).abs() < 1e-5 {
+            self.current = self.target;
+        }
+    }
+}
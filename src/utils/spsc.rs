@@ -0,0 +1,90 @@
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Storage shared by the two ends of a queue.
+struct Shared<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>, // One more than the capacity; one is always empty
+    head: AtomicUsize,                        // Next slot to read, moved only by the consumer
+    tail: AtomicUsize,                        // Next slot to write, moved only by the producer
+}
+
+// Each slot belongs to one end at a time, handed over by the release/acquire on head and tail
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        let mut head = *self.head.get_mut();
+        let tail = *self.tail.get_mut();
+        while head != tail {
+            unsafe { self.slots[head].get_mut().assume_init_drop() };
+            head = (head + 1) % self.slots.len();
+        }
+    }
+}
+
+/// Sending end of a single-producer single-consumer queue. See `spsc_queue`.
+pub struct Producer<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// Receiving end of a single-producer single-consumer queue. See `spsc_queue`.
+pub struct Consumer<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// Creates a bounded queue from one thread to another. Both ends are wait-free and never
+/// allocate after this call, so the consumer can safely live on the audio thread.
+pub fn spsc_queue<T>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    let slots = (0..capacity.max(1) + 1)
+        .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+        .collect();
+    let shared = Arc::new(Shared {
+        slots,
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+    });
+    (
+        Producer {
+            shared: shared.clone(),
+        },
+        Consumer { shared },
+    )
+}
+
+impl<T> Producer<T> {
+    /// Adds `value` to the queue, or hands it back if the queue is full.
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        let shared = &*self.shared;
+        let tail = shared.tail.load(Ordering::Relaxed);
+        let next = (tail + 1) % shared.slots.len();
+        if next == shared.head.load(Ordering::Acquire) {
+            return Err(value);
+        }
+        unsafe { (*shared.slots[tail].get()).write(value) };
+        shared.tail.store(next, Ordering::Release);
+        Ok(())
+    }
+
+    /// Most values the queue can hold at once
+    pub fn capacity(&self) -> usize {
+        self.shared.slots.len() - 1
+    }
+}
+
+impl<T> Consumer<T> {
+    /// Takes the oldest value from the queue, or None if it is empty.
+    pub fn pop(&mut self) -> Option<T> {
+        let shared = &*self.shared;
+        let head = shared.head.load(Ordering::Relaxed);
+        if head == shared.tail.load(Ordering::Acquire) {
+            return None;
+        }
+        let value = unsafe { (*shared.slots[head].get()).assume_init_read() };
+        shared
+            .head
+            .store((head + 1) % shared.slots.len(), Ordering::Release);
+        Some(value)
+    }
+}
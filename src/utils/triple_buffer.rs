@@ -0,0 +1,71 @@
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+
+const INDEX_MASK: u8 = 0b011;
+const NEW_DATA: u8 = 0b100; // Set in `back` when the writer has published since the last read
+
+/// Storage shared by the two ends of a triple buffer.
+struct Shared<T> {
+    slots: [UnsafeCell<T>; 3],
+    back: AtomicU8, // Index of the slot between the two ends, plus the NEW_DATA flag
+}
+
+// The writer and reader each own one slot and swap it with the back slot atomically,
+// so no slot is ever touched by both at once
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+/// Publishing end of a triple buffer. See `triple_buffer`.
+pub struct TripleWriter<T> {
+    shared: Arc<Shared<T>>,
+    write: usize, // Slot the writer owns
+}
+
+/// Reading end of a triple buffer. See `triple_buffer`.
+pub struct TripleReader<T> {
+    shared: Arc<Shared<T>>,
+    read: usize, // Slot the reader owns
+}
+
+/// Creates a triple buffer: one thread publishes values and another reads the most recent
+/// one. Neither side ever waits for the other, so the writer can be the audio thread.
+pub fn triple_buffer<T: Clone>(initial: T) -> (TripleWriter<T>, TripleReader<T>) {
+    let shared = Arc::new(Shared {
+        slots: [
+            UnsafeCell::new(initial.clone()),
+            UnsafeCell::new(initial.clone()),
+            UnsafeCell::new(initial),
+        ],
+        back: AtomicU8::new(1),
+    });
+    (
+        TripleWriter {
+            shared: shared.clone(),
+            write: 0,
+        },
+        TripleReader { shared, read: 2 },
+    )
+}
+
+impl<T> TripleWriter<T> {
+    /// Publishes `value`, replacing any the reader hasn't picked up yet.
+    pub fn write(&mut self, value: T) {
+        unsafe { *self.shared.slots[self.write].get() = value };
+        let previous = self
+            .shared
+            .back
+            .swap(self.write as u8 | NEW_DATA, Ordering::AcqRel);
+        self.write = (previous & INDEX_MASK) as usize;
+    }
+}
+
+impl<T> TripleReader<T> {
+    /// The most recently published value.
+    pub fn read(&mut self) -> &T {
+        if self.shared.back.load(Ordering::Relaxed) & NEW_DATA != 0 {
+            let previous = self.shared.back.swap(self.read as u8, Ordering::AcqRel);
+            self.read = (previous & INDEX_MASK) as usize;
+        }
+        unsafe { &*self.shared.slots[self.read].get() }
+    }
+}
@@ -0,0 +1,185 @@
+use std::fs;
+use std::path::Path;
+
+/// Decoded contents of a WAV file. Samples are interleaved and scaled to [-1.0, 1.0].
+#[derive(Clone, Debug)]
+pub struct WavData {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub samples: Vec<f32>,
+}
+
+impl WavData {
+    /// Averages all channels into a single mono signal.
+    pub fn to_mono(&self) -> Vec<f32> {
+        let channels = self.channels.max(1) as usize;
+        self.samples
+            .chunks(channels)
+            .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+            .collect()
+    }
+}
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_IEEE_FLOAT: u16 = 3;
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// Reads a RIFF/WAVE file containing 8/16/24/32-bit PCM or 32/64-bit float samples.
+pub fn read_wav<P: AsRef<Path>>(path: P) -> Result<WavData, String> {
+    let bytes = fs::read(path.as_ref())
+        .map_err(|e| format!("Failed to read {}: {}", path.as_ref().display(), e))?;
+    parse_wav(&bytes)
+}
+
+/// Parses the bytes of a RIFF/WAVE file. See `read_wav`.
+pub fn parse_wav(bytes: &[u8]) -> Result<WavData, String> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err("Not a RIFF/WAVE file.".to_string());
+    }
+
+    let mut format: Option<(u16, u16, u32, u16)> = None; // (format, channels, rate, bits)
+    let mut data: Option<&[u8]> = None;
+    let mut offset = 12;
+    while offset + 8 <= bytes.len() {
+        let id = &bytes[offset..offset + 4];
+        let size = u32::from_le_bytes(bytes[offset + 4..offset + 8].try_into().unwrap()) as usize;
+        let body_start = offset + 8;
+        let body_end = (body_start + size).min(bytes.len());
+        let body = &bytes[body_start..body_end];
+
+        match id {
+            b"fmt " => {
+                if body.len() < 16 {
+                    return Err("WAV fmt chunk is too short.".to_string());
+                }
+                let mut format_tag = u16::from_le_bytes([body[0], body[1]]);
+                let channels = u16::from_le_bytes([body[2], body[3]]);
+                let sample_rate = u32::from_le_bytes([body[4], body[5], body[6], body[7]]);
+                let bits = u16::from_le_bytes([body[14], body[15]]);
+                if format_tag == FORMAT_EXTENSIBLE && body.len() >= 26 {
+                    // The real format is the first two bytes of the sub-format GUID
+                    format_tag = u16::from_le_bytes([body[24], body[25]]);
+                }
+                format = Some((format_tag, channels, sample_rate, bits));
+            }
+            b"data" => data = Some(body),
+            _ => {}
+        }
+        // Chunks are padded to an even number of bytes
+        offset = body_start + size + (size & 1);
+    }
+
+    let (format_tag, channels, sample_rate, bits) = format.ok_or("WAV file has no fmt chunk.")?;
+    let data = data.ok_or("WAV file has no data chunk.")?;
+    if channels == 0 {
+        return Err("WAV file declares zero channels.".to_string());
+    }
+
+    let samples: Vec<f32> = match (format_tag, bits) {
+        (FORMAT_PCM, 8) => data.iter().map(|&b| (b as f32 - 128.0) / 128.0).collect(),
+        (FORMAT_PCM, 16) => data
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0)
+            .collect(),
+        (FORMAT_PCM, 24) => data
+            .chunks_exact(3)
+            .map(|b| (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f32 / 8_388_608.0)
+            .collect(),
+        (FORMAT_PCM, 32) => data
+            .chunks_exact(4)
+            .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2_147_483_648.0)
+            .collect(),
+        (FORMAT_IEEE_FLOAT, 32) => data
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect(),
+        (FORMAT_IEEE_FLOAT, 64) => data
+            .chunks_exact(8)
+            .map(|b| f64::from_le_bytes(b.try_into().unwrap()) as f32)
+            .collect(),
+        _ => {
+            return Err(format!(
+                "Unsupported WAV format {} with {} bits per sample.",
+                format_tag, bits
+            ))
+        }
+    };
+
+    Ok(WavData {
+        sample_rate,
+        channels,
+        samples,
+    })
+}
+
+/// Sample encodings `write_wav` can produce.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WavSampleFormat {
+    Pcm16,
+    Pcm24,
+    Float32,
+}
+
+impl WavSampleFormat {
+    fn bits(&self) -> u16 {
+        match self {
+            WavSampleFormat::Pcm16 => 16,
+            WavSampleFormat::Pcm24 => 24,
+            WavSampleFormat::Float32 => 32,
+        }
+    }
+}
+
+/// Writes `data` as a RIFF/WAVE file. PCM samples are clipped to [-1.0, 1.0].
+pub fn write_wav<P: AsRef<Path>>(
+    path: P,
+    data: &WavData,
+    format: WavSampleFormat,
+) -> Result<(), String> {
+    fs::write(path.as_ref(), encode_wav(data, format))
+        .map_err(|e| format!("Failed to write {}: {}", path.as_ref().display(), e))
+}
+
+/// Encodes `data` as the bytes of a RIFF/WAVE file. See `write_wav`.
+pub fn encode_wav(data: &WavData, format: WavSampleFormat) -> Vec<u8> {
+    let bytes_per_sample = (format.bits() / 8) as usize;
+    let data_len = data.samples.len() * bytes_per_sample;
+    let block_align = data.channels as usize * bytes_per_sample;
+    let format_tag = match format {
+        WavSampleFormat::Float32 => FORMAT_IEEE_FLOAT,
+        _ => FORMAT_PCM,
+    };
+
+    let padding = data_len & 1; // Chunks are padded to an even number of bytes
+
+    let mut bytes = Vec::with_capacity(44 + data_len + padding);
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&((36 + data_len + padding) as u32).to_le_bytes());
+    bytes.extend_from_slice(b"WAVE");
+    bytes.extend_from_slice(b"fmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    bytes.extend_from_slice(&format_tag.to_le_bytes());
+    bytes.extend_from_slice(&data.channels.to_le_bytes());
+    bytes.extend_from_slice(&data.sample_rate.to_le_bytes());
+    bytes.extend_from_slice(&(data.sample_rate * block_align as u32).to_le_bytes());
+    bytes.extend_from_slice(&(block_align as u16).to_le_bytes());
+    bytes.extend_from_slice(&format.bits().to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&(data_len as u32).to_le_bytes());
+
+    for &sample in &data.samples {
+        match format {
+            WavSampleFormat::Pcm16 => {
+                let value = (sample.clamp(-1.0, 1.0) * 32767.0).round() as i16;
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            WavSampleFormat::Pcm24 => {
+                let value = (sample.clamp(-1.0, 1.0) * 8_388_607.0).round() as i32;
+                bytes.extend_from_slice(&value.to_le_bytes()[..3]);
+            }
+            WavSampleFormat::Float32 => bytes.extend_from_slice(&sample.to_le_bytes()),
+        }
+    }
+    bytes.resize(bytes.len() + padding, 0);
+    bytes
+}
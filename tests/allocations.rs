@@ -0,0 +1,238 @@
+use rustfmsynth::synth::algorithm::{Algorithm, FeedbackMode};
+use rustfmsynth::synth::config::PlayMode;
+use rustfmsynth::synth::engine::SynthEngine;
+use rustfmsynth::synth::filter::{FilterSettings, LadderSettings};
+use rustfmsynth::synth::glide::{GlideMode, Portamento};
+use rustfmsynth::synth::lfo::{LfoRate, LfoSettings};
+use rustfmsynth::synth::modulation::{ModDestination, ModSlot, ModSource};
+use rustfmsynth::synth::note::{ControlEvent, NoteEvent, NoteSource, TimedEvent, CC_SUSTAIN};
+use rustfmsynth::synth::realtime::{EngineController, RealtimeEngine};
+use rustfmsynth::synth::sequencer::Sequencer;
+use rustfmsynth::utils::note_list::parse_note_list;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+const SAMPLE_RATE: f32 = 44100.0;
+const BUFFER_FRAMES: usize = 256;
+
+/// Passes everything to the system allocator, counting the allocations made by the thread
+/// that is currently measuring.
+struct CountingAllocator;
+
+thread_local! {
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn record_allocation() {
+    // try_with: thread locals may already be gone while a thread shuts down
+    let _ = COUNTING.try_with(|counting| {
+        if counting.get() {
+            ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        }
+    });
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        record_allocation();
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        record_allocation();
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        record_allocation();
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Number of heap allocations `f` makes on this thread.
+fn count_allocations(f: impl FnOnce()) -> usize {
+    ALLOCATIONS.with(|count| count.set(0));
+    COUNTING.with(|counting| counting.set(true));
+    f();
+    COUNTING.with(|counting| counting.set(false));
+    ALLOCATIONS.with(|count| count.get())
+}
+
+/// Short notes on top of the played chords, looped by the sequencer every 40 ms.
+const LOOP: &str = "
+0.000 0.012 72
+0.010 0.012 76 90
+0.020 0.015 79
+0.030 0.020 84 60
+";
+
+/// An engine using most of what a voice can do: LFOs (per voice and global), the mod
+/// matrix, both filters and unison, with a looping sequencer playing along.
+fn busy_engine() -> SynthEngine {
+    let mut engine = SynthEngine::new();
+    engine.set_lfos(vec![
+        LfoSettings {
+            pitch_depth: 20.0,
+            amp_depth: 0.3,
+            index_depth: 0.5,
+            ..LfoSettings::default()
+        },
+        LfoSettings {
+            rate: LfoRate::Beats(0.5),
+            per_voice: false,
+            ..LfoSettings::default()
+        },
+    ]);
+    let slots = [
+        ModSlot::new(ModSource::Lfo(1), ModDestination::FilterCutoff, 0.5),
+        ModSlot::new(ModSource::Envelope, ModDestination::Pan, 0.2),
+        ModSlot::new(
+            ModSource::Velocity,
+            ModDestination::OperatorModIndex(1),
+            0.5,
+        ),
+        ModSlot::new(ModSource::Key, ModDestination::OperatorRatio(1), 0.1),
+        ModSlot::new(ModSource::Lfo(1), ModDestination::MasterVolume, -0.2),
+    ];
+    for (index, slot) in slots.into_iter().enumerate() {
+        engine.set_mod_slot(index, Some(slot)).unwrap();
+    }
+    engine.set_ladder_filter(Some(LadderSettings::default()));
+    engine.set_voice_filter(Some(FilterSettings::default()));
+    engine.set_unison(3, 10.0, 0.8);
+
+    let mut sequencer = Sequencer::new(&parse_note_list(LOOP).unwrap());
+    sequencer.set_loop(Some((0, 40))).unwrap();
+    engine.set_sequencer(Some(sequencer));
+    engine
+}
+
+fn note(note_number: u8, is_on: bool) -> NoteEvent {
+    NoteEvent::new(note_number, 100, is_on, NoteSource::Keyboard).unwrap()
+}
+
+/// Lowest note of each round's chord
+const ROUNDS: [u8; 4] = [36, 48, 36, 60];
+
+/// The events of one round, starting at sample `now`: a chord of 48 notes from `first`,
+/// spread over the next buffer with the previous chord released, then pedal and bend
+/// changes. Enough notes make the engine steal voices.
+fn round_events(now: u64, round: usize, first: u8) -> impl Iterator<Item = TimedEvent> {
+    let chord = (0..48).flat_map(move |i| {
+        let time = now + i as u64 * 7;
+        [
+            TimedEvent::at(time, note(first + i, true)),
+            TimedEvent::at(time + 3, note(first + i + 12, false)),
+        ]
+    });
+    let pedal = ControlEvent::ControlChange {
+        controller: CC_SUSTAIN,
+        value: if round % 2 == 1 { 0 } else { 127 },
+    };
+    let bend = ControlEvent::PitchBend(round as f32 * 0.3 - 0.5);
+    chord.chain([TimedEvent::at(now + 100, pedal), bend.into()])
+}
+
+/// Runs one cycle of chords, pedal and bend changes, the way a player would.
+fn play(engine: &mut SynthEngine, output: &mut [f32], channels: usize) {
+    for (round, first) in ROUNDS.into_iter().enumerate() {
+        for event in round_events(engine.sample_clock(), round, first) {
+            engine.queue_event(event);
+        }
+        for _ in 0..2 {
+            engine.process_interleaved(output, channels, SAMPLE_RATE);
+        }
+    }
+}
+
+/// `play` through a `RealtimeEngine`, with the events sent from its controller.
+fn play_realtime(
+    realtime: &mut RealtimeEngine,
+    controller: &mut EngineController,
+    output: &mut [f32],
+) {
+    for (round, first) in ROUNDS.into_iter().enumerate() {
+        let now = controller.status().sample_clock;
+        for event in round_events(now, round, first) {
+            controller.send(event).unwrap();
+        }
+        for _ in 0..2 {
+            realtime.process_interleaved(output, 2, SAMPLE_RATE);
+        }
+    }
+}
+
+#[test]
+fn processing_does_not_allocate_after_warm_up() {
+    let mut engine = busy_engine();
+    let mut output = vec![0.0; BUFFER_FRAMES * 2];
+    // Warm up: the first notes set up state that is kept from then on
+    play(&mut engine, &mut output, 2);
+
+    let allocations = count_allocations(|| play(&mut engine, &mut output, 2));
+    assert_eq!(allocations, 0, "stereo processing allocated");
+    assert!(output.iter().any(|&sample| sample != 0.0));
+}
+
+#[test]
+fn long_and_mono_buffers_do_not_allocate() {
+    let mut engine = busy_engine();
+    // Longer than max_block_size, so the engine has to split it
+    let mut output = vec![0.0; engine.config.max_block_size * 3 + 17];
+    play(&mut engine, &mut output, 1);
+
+    let allocations = count_allocations(|| play(&mut engine, &mut output, 1));
+    assert_eq!(allocations, 0, "mono processing allocated");
+}
+
+#[test]
+fn single_sample_feedback_does_not_allocate() {
+    let mut engine = busy_engine();
+    // DX7 algorithm 4 has a three-operator loop, which runs one sample at a time
+    let algorithm = Algorithm::dx7(4, engine.operators().len()).unwrap();
+    engine.set_algorithm(algorithm).unwrap();
+    engine
+        .set_feedback_mode(FeedbackMode::SingleSample)
+        .unwrap();
+    let mut output = vec![0.0; BUFFER_FRAMES * 2];
+    play(&mut engine, &mut output, 2);
+
+    let allocations = count_allocations(|| play(&mut engine, &mut output, 2));
+    assert_eq!(allocations, 0, "single-sample feedback allocated");
+    assert!(output.iter().any(|&sample| sample != 0.0));
+}
+
+#[test]
+fn mono_legato_and_portamento_do_not_allocate() {
+    let mut engine = busy_engine();
+    engine.set_play_mode(PlayMode::Mono);
+    engine.set_legato(true);
+    engine.set_portamento(Some(Portamento::new(GlideMode::ConstantRate, 0.05)));
+    let mut output = vec![0.0; BUFFER_FRAMES * 2];
+    play(&mut engine, &mut output, 2);
+
+    let allocations = count_allocations(|| play(&mut engine, &mut output, 2));
+    assert_eq!(allocations, 0, "mono play mode allocated");
+    assert!(output.iter().any(|&sample| sample != 0.0));
+}
+
+#[test]
+fn realtime_engine_does_not_allocate() {
+    let (mut realtime, mut controller) = RealtimeEngine::new(busy_engine());
+    let mut output = vec![0.0; BUFFER_FRAMES * 2];
+    play_realtime(&mut realtime, &mut controller, &mut output);
+
+    // Sending doesn't allocate either: the command queue is set up in `RealtimeEngine::new`
+    let allocations =
+        count_allocations(|| play_realtime(&mut realtime, &mut controller, &mut output));
+    assert_eq!(allocations, 0, "the realtime engine allocated");
+    assert!(controller.status().peak > 0.0);
+}
@@ -0,0 +1,94 @@
+use rustfmsynth::synth::noise::NoiseGenerator;
+use rustfmsynth::synth::waveform::{Waveform, WaveformGenerator};
+use std::f32::consts::PI;
+
+const FFT_SIZE: usize = 4096;
+// Aliased energy relative to the harmonic energy must stay below this level
+const ALIASING_THRESHOLD_DB: f64 = -24.0;
+
+/// Renders `FFT_SIZE` samples of the waveform with exactly `cycles` periods in the window,
+/// so harmonics land on bin multiples of `cycles` without needing a window function.
+fn render(waveform: Waveform, band_limited: bool, cycles: usize, sample_rate: f32) -> Vec<f32> {
+    let mut generator = WaveformGenerator::new(waveform);
+    generator.band_limited = band_limited;
+    let frequency = cycles as f32 * sample_rate / FFT_SIZE as f32;
+    let phase_increment = 2.0 * PI * frequency / sample_rate;
+
+    let mut output = vec![0.0; FFT_SIZE];
+    let modulation = vec![0.0; FFT_SIZE];
+    let mut noise = NoiseGenerator::default();
+    // Run one buffer first so the analysed block doesn't start on a fresh phase
+    let phase_increments = vec![phase_increment; FFT_SIZE];
+    let phase = generator.generate(0.0, &phase_increments, &mut output, &modulation, &mut noise);
+    generator.generate(
+        phase,
+        &phase_increments,
+        &mut output,
+        &modulation,
+        &mut noise,
+    );
+    output
+}
+
+/// Ratio of energy outside the harmonic bins to energy in them, in dB.
+fn aliasing_db(signal: &[f32], cycles: usize) -> f64 {
+    let n = signal.len();
+    let (mut harmonic_power, mut alias_power) = (0.0f64, 0.0f64);
+    for bin in 1..n / 2 {
+        let (mut re, mut im) = (0.0f64, 0.0f64);
+        for (i, &x) in signal.iter().enumerate() {
+            let angle = -2.0 * std::f64::consts::PI * ((bin * i) % n) as f64 / n as f64;
+            re += x as f64 * angle.cos();
+            im += x as f64 * angle.sin();
+        }
+        let power = re * re + im * im;
+        if bin % cycles == 0 {
+            harmonic_power += power;
+        } else {
+            alias_power += power;
+        }
+    }
+    10.0 * (alias_power / harmonic_power).log10()
+}
+
+fn check_waveform(waveform: Waveform) {
+    for sample_rate in [44100.0, 48000.0] {
+        // An odd cycle count keeps aliases off the harmonic bins; this puts f0 around 2.5 kHz
+        let cycles = ((2500.0 * FFT_SIZE as f32 / sample_rate) as usize) | 1;
+
+        let band_limited = aliasing_db(&render(waveform, true, cycles, sample_rate), cycles);
+        let naive = aliasing_db(&render(waveform, false, cycles, sample_rate), cycles);
+
+        assert!(
+            band_limited < ALIASING_THRESHOLD_DB,
+            "{:?} at {} Hz: aliasing {:.1} dB exceeds {} dB",
+            waveform,
+            sample_rate,
+            band_limited,
+            ALIASING_THRESHOLD_DB
+        );
+        assert!(
+            band_limited < naive - 10.0,
+            "{:?} at {} Hz: band-limited {:.1} dB is not clearly better than naive {:.1} dB",
+            waveform,
+            sample_rate,
+            band_limited,
+            naive
+        );
+    }
+}
+
+#[test]
+fn band_limited_square_keeps_aliasing_low() {
+    check_waveform(Waveform::Square);
+}
+
+#[test]
+fn band_limited_sawtooth_keeps_aliasing_low() {
+    check_waveform(Waveform::Sawtooth);
+}
+
+#[test]
+fn band_limited_triangle_keeps_aliasing_low() {
+    check_waveform(Waveform::Triangle);
+}
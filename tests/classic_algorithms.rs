@@ -0,0 +1,221 @@
+use rustfmsynth::synth::algorithm::{Algorithm, FeedbackMode};
+use rustfmsynth::synth::engine::SynthEngine;
+use rustfmsynth::synth::note::{NoteEvent, NoteSource};
+use rustfmsynth::synth::patch::apply_patch;
+
+/// One algorithm as drawn in the manual, with operators numbered from 1: the carriers, the
+/// modulation chains (`"6>5>4"` means 6 modulates 5, which modulates 4) and the feedback
+/// connection.
+type Diagram = (&'static [usize], &'static str, &'static str);
+
+// DX7 owner's manual, algorithms 1-32
+const DX7: [Diagram; 32] = [
+    (&[1, 3], "2>1 6>5>4>3", "6>6"),
+    (&[1, 3], "2>1 6>5>4>3", "2>2"),
+    (&[1, 4], "3>2>1 6>5>4", "6>6"),
+    (&[1, 4], "3>2>1 6>5>4", "4>6"),
+    (&[1, 3, 5], "2>1 4>3 6>5", "6>6"),
+    (&[1, 3, 5], "2>1 4>3 6>5", "5>6"),
+    (&[1, 3], "2>1 4>3 6>5>3", "6>6"),
+    (&[1, 3], "2>1 4>3 6>5>3", "4>4"),
+    (&[1, 3], "2>1 4>3 6>5>3", "2>2"),
+    (&[1, 4], "3>2>1 5>4 6>4", "3>3"),
+    (&[1, 4], "3>2>1 5>4 6>4", "6>6"),
+    (&[1, 3], "2>1 4>3 5>3 6>3", "2>2"),
+    (&[1, 3], "2>1 4>3 5>3 6>3", "6>6"),
+    (&[1, 3], "2>1 6>4>3 5>4", "6>6"),
+    (&[1, 3], "2>1 6>4>3 5>4", "2>2"),
+    (&[1], "2>1 4>3>1 6>5>1", "6>6"),
+    (&[1], "2>1 4>3>1 6>5>1", "2>2"),
+    (&[1], "2>1 3>1 6>5>4>1", "3>3"),
+    (&[1, 4, 5], "3>2>1 6>4 6>5", "6>6"),
+    (&[1, 2, 4], "3>1 3>2 5>4 6>4", "3>3"),
+    (&[1, 2, 4, 5], "3>1 3>2 6>4 6>5", "3>3"),
+    (&[1, 3, 4, 5], "2>1 6>3 6>4 6>5", "6>6"),
+    (&[1, 2, 4, 5], "3>2 6>4 6>5", "6>6"),
+    (&[1, 2, 3, 4, 5], "6>3 6>4 6>5", "6>6"),
+    (&[1, 2, 3, 4, 5], "6>4 6>5", "6>6"),
+    (&[1, 2, 4], "3>2 5>4 6>4", "6>6"),
+    (&[1, 2, 4], "3>2 5>4 6>4", "3>3"),
+    (&[1, 3, 6], "2>1 5>4>3", "5>5"),
+    (&[1, 2, 3, 5], "4>3 6>5", "6>6"),
+    (&[1, 2, 3, 6], "5>4>3", "5>5"),
+    (&[1, 2, 3, 4, 5], "6>5", "6>6"),
+    (&[1, 2, 3, 4, 5, 6], "", "6>6"),
+];
+
+// TX81Z / DX100 manuals, algorithms 1-8
+const TX81Z: [Diagram; 8] = [
+    (&[1], "4>3>2>1", "4>4"),
+    (&[1], "3>2>1 4>2", "4>4"),
+    (&[1], "3>2>1 4>1", "4>4"),
+    (&[1], "2>1 4>3>1", "4>4"),
+    (&[1, 3], "2>1 4>3", "4>4"),
+    (&[1, 2, 3], "4>1 4>2 4>3", "4>4"),
+    (&[1, 2, 3], "4>3", "4>4"),
+    (&[1, 2, 3, 4], "", "4>4"),
+];
+
+/// `(modulator, target)` pairs of a chain string, 0-based.
+fn connections(chains: &str) -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    for chain in chains.split_whitespace() {
+        let ops: Vec<usize> = chain.split('>').map(|op| op.parse().unwrap()).collect();
+        for link in ops.windows(2) {
+            pairs.push((link[0] - 1, link[1] - 1));
+        }
+    }
+    pairs
+}
+
+fn check_diagram(name: &str, number: usize, algorithm: &Algorithm, diagram: &Diagram) {
+    let (carriers, chains, feedback) = *diagram;
+    let mut expected_carriers: Vec<usize> = carriers.iter().map(|op| op - 1).collect();
+    let mut actual_carriers = algorithm.carriers.to_vec();
+    expected_carriers.sort_unstable();
+    actual_carriers.sort_unstable();
+    assert_eq!(
+        actual_carriers, expected_carriers,
+        "{} algorithm {}: carriers",
+        name, number
+    );
+
+    let matrix = &algorithm.matrix;
+    let n = matrix.len();
+    let mut expected = vec![vec![None; n]; n];
+    for (modulator, target) in connections(chains) {
+        expected[target][modulator] = Some(1);
+    }
+    let (modulator, target) = connections(feedback)[0];
+    expected[target][modulator] = Some(2);
+    for target in 0..n {
+        for modulator in 0..n {
+            assert_eq!(
+                matrix[target][modulator],
+                expected[target][modulator],
+                "{} algorithm {}: operator {} -> operator {}",
+                name,
+                number,
+                modulator + 1,
+                target + 1
+            );
+        }
+    }
+}
+
+/// A tenth of a second of one note through `algorithm`.
+fn render(algorithm: Algorithm) -> Vec<f32> {
+    let mut engine = SynthEngine::new();
+    engine.set_algorithm(algorithm).unwrap();
+    let note = NoteEvent::new(57, 100, true, NoteSource::Keyboard).unwrap();
+    engine.queue_event(note.into());
+    let mut output = vec![0.0; 4800];
+    engine.process(&mut output, 48000.0);
+    output
+}
+
+#[test]
+fn dx7_algorithms_match_the_diagrams() {
+    for (index, diagram) in DX7.iter().enumerate() {
+        let algorithm = Algorithm::dx7(index + 1, 6).unwrap();
+        check_diagram("DX7", index + 1, &algorithm, diagram);
+    }
+}
+
+#[test]
+fn tx81z_algorithms_match_the_diagrams() {
+    for (index, diagram) in TX81Z.iter().enumerate() {
+        let algorithm = Algorithm::tx81z(index + 1, 4).unwrap();
+        check_diagram("TX81Z", index + 1, &algorithm, diagram);
+    }
+}
+
+#[test]
+fn extra_operators_are_left_unconnected() {
+    let algorithm = Algorithm::tx81z(1, 6).unwrap();
+    let matrix = &algorithm.matrix;
+    assert_eq!(matrix.len(), 6);
+    for op in 4..6 {
+        assert!(matrix[op].iter().all(Option::is_none));
+        assert!(matrix.iter().all(|row| row[op].is_none()));
+    }
+    assert!(!algorithm.carriers.contains(&4));
+}
+
+#[test]
+fn invalid_algorithm_numbers_and_operator_counts_are_rejected() {
+    assert!(Algorithm::dx7(0, 6).is_err());
+    assert!(Algorithm::dx7(33, 6).is_err());
+    assert!(Algorithm::tx81z(9, 4).is_err());
+    assert!(Algorithm::dx7(1, 4).is_err());
+    assert!(Algorithm::tx81z(1, 3).is_err());
+}
+
+#[test]
+fn every_algorithm_compiles_in_both_feedback_modes() {
+    let algorithms = (1..=32)
+        .map(|number| Algorithm::dx7(number, 6))
+        .chain((1..=8).map(|number| Algorithm::tx81z(number, 4)));
+    for algorithm in algorithms {
+        let mut algorithm = algorithm.unwrap();
+        algorithm
+            .set_feedback_mode(FeedbackMode::SingleSample)
+            .unwrap();
+        algorithm.set_feedback_mode(FeedbackMode::Unrolled).unwrap();
+    }
+}
+
+#[test]
+fn every_algorithm_hears_its_feedback() {
+    let operator_count = SynthEngine::new().operators().len();
+    let dx7 = DX7.iter().enumerate().map(|(index, diagram)| {
+        let algorithm = Algorithm::dx7(index + 1, operator_count);
+        ("DX7", index + 1, algorithm, diagram)
+    });
+    let tx81z = TX81Z.iter().enumerate().map(|(index, diagram)| {
+        let algorithm = Algorithm::tx81z(index + 1, operator_count);
+        ("TX81Z", index + 1, algorithm, diagram)
+    });
+    for (name, number, algorithm, diagram) in dx7.chain(tx81z) {
+        let algorithm = algorithm.unwrap();
+        assert_eq!(algorithm.feedback_mode(), FeedbackMode::SingleSample);
+        let mut without_feedback = algorithm.clone();
+        let (modulator, target) = connections(diagram.2)[0];
+        without_feedback.matrix[target][modulator] = None;
+
+        let with = render(algorithm);
+        let without = render(without_feedback);
+        let difference = with
+            .iter()
+            .zip(&without)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f32::max);
+        assert!(
+            difference > 0.01,
+            "{} algorithm {}: the feedback made no difference",
+            name,
+            number
+        );
+    }
+}
+
+#[test]
+fn patches_keep_the_feedback_mode_of_classic_algorithms() {
+    let mut engine = SynthEngine::new();
+    apply_patch(&mut engine, "algorithm = dx7 4").unwrap();
+    assert_eq!(
+        engine.algorithm().feedback_mode(),
+        FeedbackMode::SingleSample
+    );
+
+    // A feedback mode set before the algorithm is replaced by the algorithm's; one set after
+    // it applies
+    let mut engine = SynthEngine::new();
+    apply_patch(&mut engine, "feedback_mode = unrolled\nalgorithm = tx81z 1").unwrap();
+    assert_eq!(
+        engine.algorithm().feedback_mode(),
+        FeedbackMode::SingleSample
+    );
+    apply_patch(&mut engine, "algorithm = tx81z 1\nfeedback_mode = unrolled").unwrap();
+    assert_eq!(engine.algorithm().feedback_mode(), FeedbackMode::Unrolled);
+}
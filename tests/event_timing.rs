@@ -0,0 +1,94 @@
+use rustfmsynth::synth::engine::SynthEngine;
+use rustfmsynth::synth::note::{NoteEvent, NoteSource, TimedEvent};
+use rustfmsynth::synth::sequencer::Sequencer;
+use rustfmsynth::utils::note_list::parse_note_list;
+
+const SAMPLE_RATE: f32 = 48000.0;
+const LENGTH: usize = 2048;
+
+fn note_on() -> NoteEvent {
+    NoteEvent::new(69, 100, true, NoteSource::Keyboard).unwrap()
+}
+
+/// Renders `frames` mono samples in buffers of `buffer_frames`.
+fn render(engine: &mut SynthEngine, frames: usize, buffer_frames: usize) -> Vec<f32> {
+    let mut output = Vec::with_capacity(frames);
+    let mut buffer = vec![0.0; buffer_frames];
+    while output.len() < frames {
+        let len = buffer_frames.min(frames - output.len());
+        engine.process_interleaved(&mut buffer[..len], 1, SAMPLE_RATE);
+        output.extend_from_slice(&buffer[..len]);
+    }
+    output
+}
+
+/// Index of the first sample that isn't silent.
+fn onset(output: &[f32]) -> Option<usize> {
+    output.iter().position(|&sample| sample != 0.0)
+}
+
+/// How many samples a note takes to become audible after it starts. A sine starts at zero,
+/// so this isn't necessarily 0; a note starting at sample N must become audible this many
+/// samples after N.
+fn onset_delay() -> usize {
+    let mut engine = SynthEngine::new();
+    engine.queue_event(TimedEvent::at(0, note_on()));
+    onset(&render(&mut engine, LENGTH, 64)).expect("the note is silent")
+}
+
+#[test]
+fn timed_events_start_on_their_sample() {
+    let delay = onset_delay();
+    // Mid-buffer, on the last sample of a buffer, exactly on a buffer edge, and several
+    // buffers ahead
+    for start in [37, 63, 64, 128, 1000] {
+        for buffer_frames in [64, 100] {
+            let mut engine = SynthEngine::new();
+            engine.queue_event(TimedEvent::at(start as u64, note_on()));
+            let output = render(&mut engine, LENGTH, buffer_frames);
+            assert_eq!(
+                onset(&output),
+                Some(start + delay),
+                "event at {} in buffers of {}",
+                start,
+                buffer_frames
+            );
+        }
+    }
+}
+
+#[test]
+fn events_queued_between_buffers_keep_their_time() {
+    let delay = onset_delay();
+    let mut engine = SynthEngine::new();
+    let mut output = render(&mut engine, 128, 64);
+    // Queued after 128 samples were already played, for the middle of the next buffer
+    engine.queue_event(TimedEvent::at(150, note_on()));
+    output.extend(render(&mut engine, LENGTH, 64));
+    assert_eq!(onset(&output), Some(150 + delay));
+
+    // Times already in the past play at the start of the next buffer
+    let mut engine = SynthEngine::new();
+    let mut output = render(&mut engine, 128, 64);
+    engine.queue_event(TimedEvent::at(5, note_on()));
+    output.extend(render(&mut engine, LENGTH, 64));
+    assert_eq!(onset(&output), Some(128 + delay));
+}
+
+#[test]
+fn sequencer_notes_start_on_their_sample() {
+    let delay = onset_delay();
+    let file = parse_note_list("0.01 0.5 69 100").unwrap();
+    // 0.01 s is sample 480: on a buffer edge for buffers of 48 and 96, mid-buffer otherwise
+    for buffer_frames in [48, 96, 100, 256, 479] {
+        let mut engine = SynthEngine::new();
+        engine.set_sequencer(Some(Sequencer::new(&file)));
+        let output = render(&mut engine, LENGTH, buffer_frames);
+        assert_eq!(
+            onset(&output),
+            Some(480 + delay),
+            "buffers of {}",
+            buffer_frames
+        );
+    }
+}
@@ -0,0 +1,197 @@
+use rustfmsynth::synth::algorithm::{Algorithm, AlgorithmBuffers, FeedbackMode};
+use rustfmsynth::synth::modulation::VoiceModulation;
+use rustfmsynth::synth::operator::{Operator, OperatorState};
+
+const SAMPLE_RATE: f32 = 48000.0;
+const LENGTH: usize = 2048;
+
+/// An algorithm with a feedback loop, and the feedback connection closing it.
+struct Case {
+    name: &'static str,
+    algorithm: Algorithm,
+    feedback: (usize, usize), // (modulator, target)
+    outside: usize,           // A carrier the loop doesn't reach
+}
+
+fn cases() -> Vec<Case> {
+    // Operator 1 feeds back on itself and modulates 0; operator 2 plays on its own
+    let mut matrix = vec![vec![None; 3]; 3];
+    matrix[0][1] = Some(1);
+    matrix[1][1] = Some(2);
+    let self_feedback = Algorithm::new(matrix, vec![0, 2]).unwrap();
+    vec![
+        Case {
+            name: "self-feedback",
+            algorithm: self_feedback,
+            feedback: (1, 1),
+            outside: 2,
+        },
+        // 6>5>4 with 4 feeding back to 6, and 3>2>1 alongside
+        Case {
+            name: "DX7 algorithm 4",
+            algorithm: Algorithm::dx7(4, 6).unwrap(),
+            feedback: (3, 5),
+            outside: 0,
+        },
+        // 6>5 with 5 feeding back to 6, and 2>1 alongside
+        Case {
+            name: "DX7 algorithm 6",
+            algorithm: Algorithm::dx7(6, 6).unwrap(),
+            feedback: (4, 5),
+            outside: 0,
+        },
+    ]
+}
+
+/// Operators at different ratios, so every connection changes the sound.
+fn operators(count: usize) -> Vec<Operator> {
+    (0..count)
+        .map(|i| {
+            let mut operator = Operator::new();
+            operator.frequency_ratio = 1.0 + i as f32 * 0.5;
+            operator.modulation_index = 1.5;
+            operator
+        })
+        .collect()
+}
+
+/// Renders `LENGTH` samples of one 220 Hz note, in buffers of `buffer_sizes` taken in turn.
+/// Only the left channel is returned; every carrier is centred.
+fn render(algorithm: &Algorithm, operators: &[Operator], buffer_sizes: &[usize]) -> Vec<f32> {
+    let mut states: Vec<OperatorState> = operators
+        .iter()
+        .map(|operator| {
+            let mut state = OperatorState::new();
+            state.sync(operator);
+            state.trigger();
+            state
+        })
+        .collect();
+    let mut buffers = AlgorithmBuffers::default();
+    let frequency = vec![220.0; LENGTH];
+    let (mut left, mut right) = (vec![0.0; LENGTH], vec![0.0; LENGTH]);
+
+    let mut start = 0;
+    for &size in buffer_sizes.iter().cycle() {
+        if start == LENGTH {
+            break;
+        }
+        let end = (start + size).min(LENGTH);
+        let modulation = VoiceModulation {
+            base_frequency: &frequency[start..end],
+            operators: &[],
+        };
+        algorithm.process(
+            operators,
+            &mut states,
+            &modulation,
+            &mut buffers,
+            &mut left[start..end],
+            &mut right[start..end],
+            SAMPLE_RATE,
+        );
+        start = end;
+    }
+    left
+}
+
+fn single_sample(mut algorithm: Algorithm) -> Algorithm {
+    algorithm
+        .set_feedback_mode(FeedbackMode::SingleSample)
+        .unwrap();
+    algorithm
+}
+
+/// The same algorithm with its feedback connection removed.
+fn without_feedback(case: &Case) -> Algorithm {
+    let mut algorithm = case.algorithm.clone();
+    let (modulator, target) = case.feedback;
+    algorithm.matrix[target][modulator] = None;
+    algorithm.recompile().unwrap();
+    single_sample(algorithm)
+}
+
+fn max_difference(a: &[f32], b: &[f32]) -> f32 {
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| (a - b).abs())
+        .fold(0.0, f32::max)
+}
+
+#[test]
+fn feedback_history_carries_over_between_buffers() {
+    for case in cases() {
+        let algorithm = single_sample(case.algorithm);
+        let operators = operators(algorithm.operator_count());
+        let whole = render(&algorithm, &operators, &[LENGTH]);
+        assert!(
+            whole.iter().any(|sample| sample.abs() > 0.1),
+            "{}",
+            case.name
+        );
+        // Buffers of one and two samples take all their feedback from the history
+        for buffer_sizes in [&[1][..], &[2], &[1, 7, 64, 100, 333]] {
+            let split = render(&algorithm, &operators, buffer_sizes);
+            assert!(
+                max_difference(&whole, &split) < 1e-5,
+                "{} in buffers of {:?}",
+                case.name,
+                buffer_sizes
+            );
+        }
+    }
+}
+
+#[test]
+fn zero_feedback_sounds_like_no_feedback_connection() {
+    for case in cases() {
+        let algorithm = single_sample(case.algorithm.clone());
+        let mut operators = operators(algorithm.operator_count());
+        let reference = render(&without_feedback(&case), &operators, &[256]);
+
+        // The loop's operators still run one sample at a time, in the order they modulate
+        // each other, so without feedback they give what the plain chain gives
+        operators[case.feedback.0].feedback = 0.0;
+        let silent_loop = render(&algorithm, &operators, &[256]);
+        assert!(
+            max_difference(&reference, &silent_loop) < 1e-5,
+            "{}",
+            case.name
+        );
+
+        operators[case.feedback.0].feedback = 1.0;
+        let with_feedback = render(&algorithm, &operators, &[256]);
+        assert!(
+            max_difference(&reference, &with_feedback) > 0.01,
+            "{}: the feedback had no effect",
+            case.name
+        );
+    }
+}
+
+#[test]
+fn feedback_stays_inside_its_loop() {
+    for case in cases() {
+        // Only listen to a carrier the loop doesn't reach
+        let mut algorithm = case.algorithm.clone();
+        algorithm.carriers = vec![case.outside];
+        algorithm.recompile().unwrap();
+        let algorithm = single_sample(algorithm);
+        let mut without = without_feedback(&case);
+        without.carriers = vec![case.outside];
+        without.recompile().unwrap();
+
+        let operators = operators(algorithm.operator_count());
+        let output = render(&algorithm, &operators, &[256]);
+        assert!(
+            output.iter().any(|sample| sample.abs() > 0.1),
+            "{}",
+            case.name
+        );
+        assert!(
+            max_difference(&output, &render(&without, &operators, &[256])) < 1e-6,
+            "{}",
+            case.name
+        );
+    }
+}
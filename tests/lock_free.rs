@@ -0,0 +1,122 @@
+use rustfmsynth::utils::spsc::spsc_queue;
+use rustfmsynth::utils::triple_buffer::triple_buffer;
+use std::sync::Arc;
+use std::thread;
+
+const COUNT: u64 = 100_000;
+
+#[test]
+fn queue_keeps_order_across_threads() {
+    let (mut producer, mut consumer) = spsc_queue(16);
+    let sender = thread::spawn(move || {
+        for value in 0..COUNT {
+            let mut value = value;
+            // A full queue hands the value back; try again once the consumer catches up
+            while let Err(returned) = producer.push(value) {
+                value = returned;
+                thread::yield_now();
+            }
+        }
+    });
+
+    let mut expected = 0;
+    while expected < COUNT {
+        match consumer.pop() {
+            Some(value) => {
+                assert_eq!(value, expected);
+                expected += 1;
+            }
+            None => thread::yield_now(),
+        }
+    }
+    sender.join().unwrap();
+    assert_eq!(consumer.pop(), None);
+}
+
+#[test]
+fn queue_reports_full_and_empty() {
+    let (mut producer, mut consumer) = spsc_queue(3);
+    assert_eq!(producer.capacity(), 3);
+    assert_eq!(consumer.pop(), None);
+
+    // Go round the ring a few times so the checks also hold after wrapping
+    for round in 0..5 {
+        for i in 0..3 {
+            assert_eq!(producer.push(round * 10 + i), Ok(()));
+        }
+        assert_eq!(producer.push(99), Err(99));
+        assert_eq!(consumer.pop(), Some(round * 10));
+        assert_eq!(producer.push(round * 10 + 3), Ok(()));
+        assert_eq!(producer.push(99), Err(99));
+        for i in 1..4 {
+            assert_eq!(consumer.pop(), Some(round * 10 + i));
+        }
+        assert_eq!(consumer.pop(), None);
+    }
+
+    // A capacity of zero still holds one value
+    let (mut producer, mut consumer) = spsc_queue(0);
+    assert_eq!(producer.capacity(), 1);
+    assert_eq!(producer.push(1), Ok(()));
+    assert_eq!(producer.push(2), Err(2));
+    assert_eq!(consumer.pop(), Some(1));
+}
+
+#[test]
+fn queue_drops_values_still_queued() {
+    let value = Arc::new(());
+    let (mut producer, mut consumer) = spsc_queue(4);
+    // Move the ring's start so the queued values wrap past the end of the slots
+    for _ in 0..3 {
+        producer.push(value.clone()).unwrap();
+        drop(consumer.pop());
+    }
+    for _ in 0..4 {
+        producer.push(value.clone()).unwrap();
+    }
+    drop(consumer.pop());
+    assert_eq!(Arc::strong_count(&value), 4);
+
+    // Dropping either end first leaves the values to the other
+    drop(consumer);
+    assert_eq!(Arc::strong_count(&value), 4);
+    drop(producer);
+    assert_eq!(Arc::strong_count(&value), 1);
+}
+
+#[test]
+fn triple_buffer_reader_sees_the_latest_whole_value() {
+    // Every element of a value is the same, so a torn read would show up as a mix
+    let (mut writer, mut reader) = triple_buffer([0u64; 32]);
+    let sender = thread::spawn(move || {
+        for value in 1..=COUNT {
+            writer.write([value; 32]);
+        }
+    });
+
+    let mut last = 0;
+    while last < COUNT {
+        let value = *reader.read();
+        assert!(
+            value.iter().all(|&element| element == value[0]),
+            "torn value"
+        );
+        assert!(value[0] >= last, "went back from {} to {}", last, value[0]);
+        last = value[0];
+    }
+    sender.join().unwrap();
+    assert_eq!(*reader.read(), [COUNT; 32]);
+}
+
+#[test]
+fn triple_buffer_keeps_the_value_between_writes() {
+    let (mut writer, mut reader) = triple_buffer(String::from("initial"));
+    assert_eq!(reader.read(), "initial");
+    writer.write(String::from("first"));
+    writer.write(String::from("second"));
+    // Only the newest write is seen, and reading again without a write gives it again
+    assert_eq!(reader.read(), "second");
+    assert_eq!(reader.read(), "second");
+    writer.write(String::from("third"));
+    assert_eq!(reader.read(), "third");
+}
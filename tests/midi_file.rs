@@ -0,0 +1,349 @@
+use rustfmsynth::synth::note::ChannelEvent;
+use rustfmsynth::synth::sequencer::{Sequencer, TempoMap};
+use rustfmsynth::utils::midi_file::{parse_midi_file, Division, MidiEvent, MidiMessage};
+
+const END_OF_TRACK: [u8; 4] = [0x00, 0xFF, 0x2F, 0x00];
+
+/// A Standard MIDI File with the given header fields and raw MTrk bodies.
+fn smf(format: u16, division: u16, tracks: &[Vec<u8>]) -> Vec<u8> {
+    let mut bytes = b"MThd".to_vec();
+    bytes.extend_from_slice(&6u32.to_be_bytes());
+    bytes.extend_from_slice(&format.to_be_bytes());
+    bytes.extend_from_slice(&(tracks.len() as u16).to_be_bytes());
+    bytes.extend_from_slice(&division.to_be_bytes());
+    for track in tracks {
+        bytes.extend_from_slice(b"MTrk");
+        bytes.extend_from_slice(&(track.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(track);
+    }
+    bytes
+}
+
+/// A track body from raw event bytes, closed with an end-of-track event.
+fn track(events: &[u8]) -> Vec<u8> {
+    [events, &END_OF_TRACK].concat()
+}
+
+fn note_on(tick: u64, note: u8, velocity: u8) -> MidiEvent {
+    MidiEvent {
+        tick,
+        message: MidiMessage::NoteOn {
+            channel: 0,
+            note,
+            velocity,
+        },
+    }
+}
+
+fn note_off(tick: u64, note: u8) -> MidiEvent {
+    MidiEvent {
+        tick,
+        message: MidiMessage::NoteOff { channel: 0, note },
+    }
+}
+
+/// A type 0 file at 480 ticks per quarter with a note on tick 0 and another on tick 480.
+fn two_notes() -> Vec<u8> {
+    smf(
+        0,
+        480,
+        &[track(&[
+            0x00, 0x90, 60, 100, // Note on, tick 0
+            0x83, 0x60, 0x90, 62, 100, // Note on, tick 480
+            0x83, 0x60, 0x80, 60, 0, // Note off, tick 960
+            0x00, 0x80, 62, 0,
+        ])],
+    )
+}
+
+#[test]
+fn running_status_and_variable_length_deltas_are_resolved() {
+    let file = parse_midi_file(&smf(
+        0,
+        96,
+        &[track(&[
+            0x00, 0x91, 60, 100, // Note on, channel 1
+            0x81, 0x00, 64, 90, // Running status, delta 128
+            0x83, 0xFF, 0x7F, 60, 0, // Running status, velocity 0 = note off, delta 65535
+            0x00, 0xE1, 0x00, 0x40, // Pitch bend centre
+            0x7F, 0x00, 0x00, // Running status, lowest bend, delta 127
+        ])],
+    ))
+    .unwrap();
+
+    assert_eq!(file.format, 0);
+    assert_eq!(file.division, Division::TicksPerQuarter(96));
+    let expected = vec![
+        (
+            0,
+            MidiMessage::NoteOn {
+                channel: 1,
+                note: 60,
+                velocity: 100,
+            },
+        ),
+        (
+            128,
+            MidiMessage::NoteOn {
+                channel: 1,
+                note: 64,
+                velocity: 90,
+            },
+        ),
+        (
+            65663,
+            MidiMessage::NoteOff {
+                channel: 1,
+                note: 60,
+            },
+        ),
+        (
+            65663,
+            MidiMessage::PitchBend {
+                channel: 1,
+                value: 0,
+            },
+        ),
+        (
+            65790,
+            MidiMessage::PitchBend {
+                channel: 1,
+                value: -8192,
+            },
+        ),
+    ];
+    let events: Vec<(u64, MidiMessage)> = file.tracks[0]
+        .iter()
+        .map(|event| (event.tick, event.message))
+        .collect();
+    assert_eq!(events, expected);
+}
+
+#[test]
+fn meta_and_sysex_events_are_skipped() {
+    let file = parse_midi_file(&smf(
+        0,
+        480,
+        &[track(&[
+            0x00, 0xFF, 0x03, 0x05, b'P', b'i', b'a', b'n', b'o', // Track name
+            0x00, 0xF0, 0x03, 0x43, 0x10, 0xF7, // SysEx
+            0x10, 0xF7, 0x02, 0x01, 0x02, // Escaped bytes
+            0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20, // Tempo 500000
+            0x00, 0xC0, 0x05, // Program change, one data byte
+            0x00, 0xA0, 60, 50, // Polyphonic aftertouch
+            0x00, 0xD0, 70, // Channel pressure, one data byte
+            0x20, 0x90, 60, 100,
+        ])],
+    ))
+    .unwrap();
+
+    assert_eq!(
+        file.tracks[0],
+        vec![
+            MidiEvent {
+                tick: 16,
+                message: MidiMessage::Tempo(500_000),
+            },
+            MidiEvent {
+                tick: 16,
+                message: MidiMessage::ChannelPressure {
+                    channel: 0,
+                    value: 70,
+                },
+            },
+            note_on(48, 60, 100),
+        ]
+    );
+
+    // Meta events and SysEx cancel running status
+    for skipped in [&[0x00, 0xFF, 0x01, 0x00][..], &[0x00, 0xF0, 0x01, 0xF7]] {
+        let events = [&[0x00, 0x90, 60, 100][..], skipped, &[0x00, 61, 100]].concat();
+        assert!(parse_midi_file(&smf(0, 480, &[track(&events)])).is_err());
+    }
+}
+
+#[test]
+fn format_1_files_keep_their_tracks_apart() {
+    let tempo_track = track(&[0x00, 0xFF, 0x51, 0x03, 0x03, 0xD0, 0x90]); // 250000
+    let first = track(&[0x00, 0x90, 60, 100, 0x60, 0x80, 60, 0]);
+    let second = track(&[0x30, 0x92, 67, 80, 0x60, 0x92, 67, 0]);
+    let mut bytes = smf(1, 96, &[tempo_track, first]);
+    // An unknown chunk between tracks is skipped
+    bytes.extend_from_slice(b"XFIH\x00\x00\x00\x02ab");
+    bytes.extend_from_slice(b"MTrk");
+    bytes.extend_from_slice(&(second.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(&second);
+    bytes[11] = 3; // Track count
+
+    let file = parse_midi_file(&bytes).unwrap();
+    assert_eq!(file.format, 1);
+    assert_eq!(file.tracks.len(), 3);
+    assert_eq!(
+        file.tracks[0],
+        vec![MidiEvent {
+            tick: 0,
+            message: MidiMessage::Tempo(250_000),
+        }]
+    );
+    assert_eq!(file.tracks[1], vec![note_on(0, 60, 100), note_off(96, 60)]);
+    let second_track: Vec<u64> = file.tracks[2].iter().map(|event| event.tick).collect();
+    assert_eq!(second_track, vec![48, 144]);
+
+    // The tempo from the first track applies to the notes of the others
+    let sequencer = Sequencer::new(&file);
+    assert_eq!(sequencer.length_ticks(), 144);
+    assert!((sequencer.duration() - 0.375).abs() < 1e-9);
+}
+
+#[test]
+fn broken_files_are_rejected() {
+    let valid = two_notes();
+    let broken = [
+        b"MThx\x00\x00\x00\x06\x00\x00\x00\x01\x01\xE0".to_vec(),
+        smf(2, 480, &[track(&[])]),
+        smf(0, 0, &[track(&[])]),
+        smf(0, 0xE700, &[track(&[])]), // SMPTE with zero ticks per frame
+        smf(0, 480, &[track(&[0x00, 0x60, 0x40])]), // Data byte without a status
+        smf(0, 480, &[track(&[0x00, 0xF4])]), // Undefined status
+        smf(
+            0,
+            480,
+            &[track(&[0xFF, 0xFF, 0xFF, 0xFF, 0x7F, 0x90, 60, 1])],
+        ), // 5-byte delta
+        smf(0, 480, &[vec![0x00, 0x90, 60]]), // Ends inside an event
+        smf(0, 480, &[vec![0x00, 0xFF, 0x01, 0x10, b'a']]), // Meta longer than the track
+        valid[..valid.len() - 1].to_vec(), // Track chunk longer than the file
+    ];
+    for (i, bytes) in broken.iter().enumerate() {
+        assert!(parse_midi_file(bytes).is_err(), "broken file {}", i);
+    }
+
+    // A header declaring more tracks than the file holds
+    let mut missing_track = valid.clone();
+    missing_track[11] = 2;
+    assert!(parse_midi_file(&missing_track).is_err());
+}
+
+#[test]
+fn truncated_and_corrupted_files_never_panic() {
+    let valid = two_notes();
+    for len in 0..valid.len() {
+        assert!(
+            parse_midi_file(&valid[..len]).is_err(),
+            "truncated to {}",
+            len
+        );
+    }
+
+    // Overwrite bytes one at a time with values likely to confuse the parser
+    for position in 0..valid.len() {
+        for value in [0x00, 0x7F, 0x80, 0xF0, 0xFF] {
+            let mut corrupted = valid.clone();
+            corrupted[position] = value;
+            let _ = parse_midi_file(&corrupted);
+        }
+    }
+}
+
+#[test]
+fn tempo_map_follows_tempo_changes() {
+    let file = parse_midi_file(&smf(
+        0,
+        480,
+        &[track(&[
+            0x87, 0x40, 0xFF, 0x51, 0x03, 0x03, 0xD0, 0x90, // 250000 (240 BPM) at tick 960
+            0x87, 0x40, 0xFF, 0x51, 0x03, 0x0F, 0x42, 0x40, // 1000000 (60 BPM) at tick 1920
+        ])],
+    ))
+    .unwrap();
+    let map = TempoMap::new(&file);
+
+    // 120 BPM until the first change: half a second per quarter
+    assert!((map.seconds_at(480) - 0.5).abs() < 1e-9);
+    assert!((map.seconds_at(960) - 1.0).abs() < 1e-9);
+    assert!((map.seconds_at(1440) - 1.25).abs() < 1e-9);
+    assert!((map.seconds_at(1920) - 1.5).abs() < 1e-9);
+    assert!((map.seconds_at(2400) - 2.5).abs() < 1e-9);
+    assert_eq!(map.bpm_at(959), 120.0);
+    assert_eq!(map.bpm_at(960), 240.0);
+    assert_eq!(map.bpm_at(5000), 60.0);
+
+    // SMPTE time ignores tempo: 25 frames of 40 ticks make a second
+    let file = parse_midi_file(&smf(0, 0xE728, &[track(&[])])).unwrap();
+    assert_eq!(
+        file.division,
+        Division::Smpte {
+            frames_per_second: 25,
+            ticks_per_frame: 40,
+        }
+    );
+    assert!((TempoMap::new(&file).seconds_at(1500) - 1.5).abs() < 1e-9);
+}
+
+/// Frames and note numbers of the note-ons the sequencer emits over `frames` samples at
+/// 1 kHz, and the frames at which it released every note.
+fn advance(sequencer: &mut Sequencer, frames: usize) -> (Vec<(usize, u8)>, Vec<usize>) {
+    let mut notes = Vec::new();
+    let mut releases = Vec::new();
+    sequencer.advance(frames, 1000.0, |frame, event| match event {
+        ChannelEvent::Note(note) if note.is_on => notes.push((frame, note.note_number)),
+        ChannelEvent::Control(_) if !releases.contains(&frame) => releases.push(frame),
+        _ => {}
+    });
+    (notes, releases)
+}
+
+#[test]
+fn loops_wrap_on_the_exact_frame() {
+    let file = parse_midi_file(&two_notes()).unwrap();
+    let mut sequencer = Sequencer::new(&file);
+    // One second: the notes at 0 s and 0.5 s
+    sequencer.set_loop(Some((0, 960))).unwrap();
+    assert!(sequencer.set_loop(Some((960, 960))).is_err());
+
+    let (notes, releases) = advance(&mut sequencer, 1250);
+    assert_eq!(notes, vec![(0, 60), (500, 62), (1000, 60)]);
+    assert_eq!(releases, vec![1000]);
+    assert!((sequencer.position() - 0.25).abs() < 1e-9);
+
+    // The next buffer picks up where the last one stopped, wrapping again at 2 s
+    let (notes, releases) = advance(&mut sequencer, 1000);
+    assert_eq!(notes, vec![(250, 62), (750, 60)]);
+    assert_eq!(releases, vec![750]);
+    assert!(!sequencer.is_finished());
+
+    // A loop that starts later comes back to its start tick, not the file start
+    let mut sequencer = Sequencer::new(&file);
+    sequencer.set_loop(Some((480, 960))).unwrap();
+    let (notes, _) = advance(&mut sequencer, 1600);
+    assert_eq!(notes, vec![(0, 60), (500, 62), (1000, 62), (1500, 62)]);
+
+    // Without a loop, playback ends after the last event
+    let mut sequencer = Sequencer::new(&file);
+    let (notes, _) = advance(&mut sequencer, 2000);
+    assert_eq!(notes, vec![(0, 60), (500, 62)]);
+    assert!(sequencer.is_finished());
+}
+
+#[test]
+fn note_ons_without_note_offs_do_not_overflow() {
+    // 300 note-ons of one drum, 10 ticks apart, with no note-offs at all
+    let mut events = vec![0x00, 0x99, 36, 100];
+    for _ in 1..300 {
+        events.extend_from_slice(&[0x0A, 36, 100]);
+    }
+    let file = parse_midi_file(&smf(0, 480, &[track(&events)])).unwrap();
+    let mut sequencer = Sequencer::new(&file);
+    sequencer.set_loop(Some((0, 3000))).unwrap();
+
+    // At the loop end every counted note is released, which is plenty for every voice
+    let mut note_ons = 0;
+    let mut note_offs = 0;
+    sequencer.advance(3200, 1000.0, |_, event| match event {
+        ChannelEvent::Note(note) if note.is_on => note_ons += 1,
+        ChannelEvent::Note(note) if note.note_number == 36 => note_offs += 1,
+        _ => {}
+    });
+    assert!(note_ons > 300);
+    assert_eq!(note_offs, 255);
+}
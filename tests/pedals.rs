@@ -0,0 +1,153 @@
+use rustfmsynth::synth::config::{PlayMode, VoiceStealingStrategy};
+use rustfmsynth::synth::engine::SynthEngine;
+use rustfmsynth::synth::note::{
+    ControlEvent, NoteEvent, NoteSource, TimedEvent, CC_SOSTENUTO, CC_SUSTAIN,
+};
+use rustfmsynth::synth::voice::Voice;
+
+const SAMPLE_RATE: f32 = 48000.0;
+
+fn note(note_number: u8, is_on: bool) -> TimedEvent {
+    NoteEvent::new(note_number, 100, is_on, NoteSource::Keyboard)
+        .unwrap()
+        .into()
+}
+
+fn pedal(controller: u8, value: u8) -> TimedEvent {
+    ControlEvent::ControlChange { controller, value }.into()
+}
+
+/// Applies `events` at the start of the next buffer, then renders `frames` mono samples.
+fn play(engine: &mut SynthEngine, events: &[TimedEvent], frames: usize) {
+    for &event in events {
+        engine.queue_event(event);
+    }
+    let mut output = vec![0.0; frames];
+    engine.process_interleaved(&mut output, 1, SAMPLE_RATE);
+}
+
+/// The voices sounding `note_number`, including releasing ones.
+fn voices(engine: &SynthEngine, note_number: u8) -> Vec<&Voice> {
+    engine
+        .voices
+        .iter()
+        .filter(|voice| voice.note_number == note_number && !voice.is_finished())
+        .collect()
+}
+
+/// Whether a voice is holding `note_number` at full level, not releasing it.
+fn holds(engine: &SynthEngine, note_number: u8) -> bool {
+    voices(engine, note_number)
+        .iter()
+        .any(|voice| !voice.is_releasing() && voice.level() > 0.5)
+}
+
+#[test]
+fn sustain_keeps_released_keys_sounding_until_the_pedal_lifts() {
+    let mut engine = SynthEngine::new();
+    play(&mut engine, &[note(60, true), pedal(CC_SUSTAIN, 127)], 4800);
+    play(&mut engine, &[note(60, false)], 4800);
+    assert!(holds(&engine, 60), "the pedal didn't hold the note");
+
+    // 64 still counts as down; anything below lets go
+    play(&mut engine, &[pedal(CC_SUSTAIN, 64)], 4800);
+    assert!(holds(&engine, 60), "CC64 at 64 released the note");
+    play(&mut engine, &[pedal(CC_SUSTAIN, 63)], 480);
+    assert!(
+        !holds(&engine, 60),
+        "lifting the pedal didn't release the note"
+    );
+    assert!(voices(&engine, 60)[0].is_releasing());
+
+    // Without the pedal, a note-off releases at once
+    play(&mut engine, &[note(62, true)], 4800);
+    play(&mut engine, &[note(62, false)], 480);
+    assert!(!holds(&engine, 62));
+}
+
+#[test]
+fn sostenuto_holds_only_the_keys_down_when_it_was_pressed() {
+    let mut engine = SynthEngine::new();
+    play(
+        &mut engine,
+        &[note(60, true), pedal(CC_SOSTENUTO, 127)],
+        4800,
+    );
+    // Played after the pedal went down, so it isn't caught
+    play(&mut engine, &[note(64, true)], 4800);
+    play(&mut engine, &[note(60, false), note(64, false)], 4800);
+    assert!(
+        holds(&engine, 60),
+        "sostenuto didn't hold the note held when pressed"
+    );
+    assert!(!holds(&engine, 64), "sostenuto held a note played after it");
+
+    play(&mut engine, &[pedal(CC_SOSTENUTO, 0)], 480);
+    assert!(
+        !holds(&engine, 60),
+        "lifting sostenuto didn't release the note"
+    );
+}
+
+#[test]
+fn released_first_steals_a_pedal_held_voice_before_a_held_key() {
+    let mut engine = SynthEngine::new();
+    engine.config.voice_stealing = VoiceStealingStrategy::ReleasedFirst;
+    engine.voices.truncate(4);
+    play(&mut engine, &[pedal(CC_SUSTAIN, 127)], 64);
+    for note_number in [60, 62, 64, 65] {
+        play(&mut engine, &[note(note_number, true)], 64);
+    }
+    // 64's key goes up but the pedal keeps it, so every voice is still busy
+    play(&mut engine, &[note(64, false)], 64);
+    let held_by_pedal = engine
+        .voices
+        .iter()
+        .position(|voice| voice.note_number == 64)
+        .unwrap();
+
+    // Long enough for the steal fade to finish
+    play(&mut engine, &[note(67, true)], 4800);
+    assert_eq!(engine.voices[held_by_pedal].note_number, 67);
+    for note_number in [60, 62, 65] {
+        assert!(
+            holds(&engine, note_number),
+            "note {} was stolen",
+            note_number
+        );
+    }
+}
+
+#[test]
+fn notes_played_again_under_the_pedal_outlast_it() {
+    for strategy in [
+        VoiceStealingStrategy::ReleasedFirst,
+        VoiceStealingStrategy::SameNote,
+    ] {
+        let mut engine = SynthEngine::new();
+        engine.config.voice_stealing = strategy;
+        play(&mut engine, &[pedal(CC_SUSTAIN, 127), note(60, true)], 4800);
+        play(&mut engine, &[note(60, false)], 4800);
+        // The key goes down again while the pedal still holds the first note
+        play(&mut engine, &[note(60, true)], 4800);
+
+        play(&mut engine, &[pedal(CC_SUSTAIN, 0)], 480);
+        let down: Vec<_> = voices(&engine, 60)
+            .into_iter()
+            .filter(|voice| voice.is_key_down())
+            .collect();
+        assert_eq!(down.len(), 1, "{:?}: the retriggered note", strategy);
+        assert!(!down[0].is_releasing(), "{:?}", strategy);
+    }
+}
+
+#[test]
+fn changing_the_play_mode_lets_go_of_pedal_held_notes() {
+    let mut engine = SynthEngine::new();
+    play(&mut engine, &[pedal(CC_SUSTAIN, 127), note(60, true)], 4800);
+    play(&mut engine, &[note(60, false)], 4800);
+    engine.set_play_mode(PlayMode::Mono);
+    // Already released, so lifting the pedal has nothing left to release
+    assert!(engine.voices.iter().all(|voice| !voice.sustained));
+    assert!(voices(&engine, 60)[0].is_releasing());
+}
@@ -0,0 +1,221 @@
+use rustfmsynth::synth::engine::SynthEngine;
+use rustfmsynth::synth::patch::apply_patch;
+use rustfmsynth::synth::sequencer::Sequencer;
+use rustfmsynth::utils::midi_file::{MidiEvent, MidiMessage};
+use rustfmsynth::utils::note_list::parse_note_list;
+use rustfmsynth::utils::wav::{encode_wav, parse_wav, WavData, WavSampleFormat};
+
+const PATCH: &str = "
+algorithm = stack_2
+unison = 2 8 0.5
+
+[operator 0]
+envelope = 0.01 0.2 0.6 0.1
+
+[operator 1]
+waveform = noise
+gain = 0.3
+";
+
+const NOTES: &str = "
+# start duration note [velocity]
+0.0  0.25 60
+0.1  0.3  64 80
+0.25 0.2  60 127   # Starts on the tick the first note ends
+";
+
+/// Renders `NOTES` through `PATCH` the way the offline renderer does.
+fn render(seed: u64) -> Vec<u8> {
+    let mut engine = SynthEngine::new();
+    engine.set_noise_seed(seed);
+    apply_patch(&mut engine, PATCH).unwrap();
+    engine.set_sequencer(Some(Sequencer::new(&parse_note_list(NOTES).unwrap())));
+
+    let sample_rate = 44100;
+    let mut buffer = vec![0.0; 256 * 2];
+    let mut samples = Vec::new();
+    for _ in 0..(sample_rate as usize / 256) {
+        engine.process_interleaved(&mut buffer, 2, sample_rate as f32);
+        samples.extend_from_slice(&buffer);
+    }
+    let data = WavData {
+        sample_rate,
+        channels: 2,
+        samples,
+    };
+    encode_wav(&data, WavSampleFormat::Float32)
+}
+
+fn round_trip(samples: &[f32], channels: u16, format: WavSampleFormat) -> WavData {
+    let data = WavData {
+        sample_rate: 48000,
+        channels,
+        samples: samples.to_vec(),
+    };
+    let wav = parse_wav(&encode_wav(&data, format)).unwrap();
+    assert_eq!(wav.sample_rate, 48000);
+    assert_eq!(wav.channels, channels);
+    assert_eq!(wav.samples.len(), samples.len());
+    wav
+}
+
+#[test]
+fn wav_files_round_trip() {
+    let samples = [0.0, 0.5, -0.5, 1.0, -1.0, 0.123_456, -0.987_654];
+    let clipped = [1.5, -2.0];
+
+    for (format, step) in [
+        (WavSampleFormat::Pcm16, 1.0 / 32767.0),
+        (WavSampleFormat::Pcm24, 1.0 / 8_388_607.0),
+    ] {
+        let wav = round_trip(&samples, 1, format);
+        // Rounding plus the slightly different write and read scales: within two steps
+        for (read, written) in wav.samples.iter().zip(&samples) {
+            assert!(
+                (read - written).abs() <= 2.0 * step,
+                "{:?}: {}",
+                format,
+                written
+            );
+        }
+        // Out of range samples are clipped rather than wrapping around
+        let wav = round_trip(&clipped, 2, format);
+        assert!(wav.samples[0] > 1.0 - 2.0 * step && wav.samples[1] < -1.0 + 2.0 * step);
+    }
+
+    let float = [0.25, -0.75, 1.5, 1e-7, -3.0, 0.333];
+    assert_eq!(
+        round_trip(&float, 2, WavSampleFormat::Float32).samples,
+        float
+    );
+
+    // An odd number of 24-bit bytes gets a pad byte
+    let padded = encode_wav(
+        &WavData {
+            sample_rate: 48000,
+            channels: 1,
+            samples: vec![0.5],
+        },
+        WavSampleFormat::Pcm24,
+    );
+    assert_eq!(padded.len(), 44 + 4);
+    assert_eq!(
+        round_trip(&[0.5], 1, WavSampleFormat::Pcm24).samples.len(),
+        1
+    );
+}
+
+#[test]
+fn note_lists_put_note_offs_first_on_a_shared_tick() {
+    let file = parse_note_list(NOTES).unwrap();
+    let events: Vec<(u64, bool, u8)> = file.tracks[0]
+        .iter()
+        .map(|event| match event.message {
+            MidiMessage::NoteOn { note, .. } => (event.tick, true, note),
+            MidiMessage::NoteOff { note, .. } => (event.tick, false, note),
+            _ => panic!("unexpected message {:?}", event.message),
+        })
+        .collect();
+    assert_eq!(
+        events,
+        vec![
+            (0, true, 60),
+            (100, true, 64),
+            (250, false, 60),
+            (250, true, 60),
+            (400, false, 64),
+            (450, false, 60),
+        ]
+    );
+    assert_eq!(
+        file.tracks[0][1],
+        MidiEvent {
+            tick: 100,
+            message: MidiMessage::NoteOn {
+                channel: 0,
+                note: 64,
+                velocity: 80,
+            },
+        }
+    );
+}
+
+#[test]
+fn note_list_errors_name_the_line() {
+    for (text, message) in [
+        ("0 1", "Expected"),
+        ("0 1 60 100 5", "Expected"),
+        ("zero 1 60", "Start"),
+        ("0 long 60", "Duration"),
+        ("0 1 128", "Note"),
+        ("0 1 C4", "Note"),
+        ("0 1 60 0", "Velocity"),
+        ("0 1 60 128", "Velocity"),
+        ("-1 1 60", "negative"),
+        ("0 -1 60", "negative"),
+    ] {
+        let error = parse_note_list(&format!("# header\n\n{}\n", text)).unwrap_err();
+        assert!(error.starts_with("Note list line 3:"), "{}", error);
+        assert!(error.contains(message), "`{}` gave {}", text, error);
+    }
+}
+
+#[test]
+fn patch_errors_name_the_line() {
+    for (text, message) in [
+        ("colour = blue", "Unknown setting"),
+        ("master_volume", "key = value"),
+        ("master_volume = loud", "not a valid number"),
+        ("algorithm = dx9 1", "Unknown algorithm"),
+        ("connection = 1", "modulator target"),
+        ("connection = 0 99", "out of range"),
+        ("play_mode = duo", "Unknown play mode"),
+        ("legato = maybe", "true or false"),
+        ("[voice 1]", "Unknown section"),
+        ("[operator 99]", "out of range"),
+        ("[operator 0", "missing `]`"),
+        ("[operator 0]\nwaveform = cosine", "Unknown waveform"),
+        (
+            "[operator 0]\nenvelope = 1 2 3",
+            "attack decay sustain release",
+        ),
+        ("[operator 0]\nenvelope = 0.001 0 1 0.3", "above 0 seconds"),
+        (
+            "[operator 0]\nenvelope = 0.01 0.1 0.5 -1",
+            "above 0 seconds",
+        ),
+        (
+            "[operator 0]\nenvelope = NaN 0.1 0.5 0.2",
+            "above 0 seconds",
+        ),
+        ("[operator 0]\nenvelope = 0.01 0.1 1.5 0.2", "sustain"),
+        (
+            "[operator 0]\nmaster_volume = 1",
+            "Unknown operator setting",
+        ),
+    ] {
+        let mut engine = SynthEngine::new();
+        let error = apply_patch(&mut engine, &format!("# comment\n{}", text)).unwrap_err();
+        let line = text.lines().count() + 1;
+        assert!(
+            error.starts_with(&format!("Patch line {}:", line)),
+            "{}",
+            error
+        );
+        assert!(error.contains(message), "`{}` gave {}", text, error);
+    }
+
+    // Connections are checked once the whole patch is read
+    let mut engine = SynthEngine::new();
+    assert!(apply_patch(&mut engine, "connection = 0 0 1\ncarriers = 0").is_err());
+}
+
+#[test]
+fn renders_are_reproducible() {
+    let first = render(7);
+    assert_eq!(first, render(7), "the same inputs rendered differently");
+    assert_ne!(first, render(8), "the noise seed was ignored");
+
+    let wav = parse_wav(&first).unwrap();
+    assert!(wav.samples.iter().any(|&sample| sample.abs() > 0.01));
+}
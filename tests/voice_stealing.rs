@@ -0,0 +1,200 @@
+use rustfmsynth::synth::config::VoiceStealingStrategy;
+use rustfmsynth::synth::engine::SynthEngine;
+use rustfmsynth::synth::note::{NoteEvent, NoteSource, TimedEvent};
+
+const SAMPLE_RATE: f32 = 48000.0;
+/// Longer than the default 5 ms steal fade
+const FADE_DONE: usize = 480;
+
+/// An engine with only `voices` voices, so a few notes use them all.
+fn small_engine(voices: usize, strategy: VoiceStealingStrategy) -> SynthEngine {
+    let mut engine = SynthEngine::new();
+    engine.voices.truncate(voices);
+    engine.config.voice_stealing = strategy;
+    engine
+}
+
+fn note(note_number: u8, is_on: bool) -> TimedEvent {
+    NoteEvent::new(note_number, 100, is_on, NoteSource::Keyboard)
+        .unwrap()
+        .into()
+}
+
+/// Applies `event` at the start of the next buffer, then renders `frames` mono samples.
+fn play(engine: &mut SynthEngine, event: TimedEvent, frames: usize) -> Vec<f32> {
+    engine.queue_event(event);
+    let mut output = vec![0.0; frames];
+    engine.process_interleaved(&mut output, 1, SAMPLE_RATE);
+    output
+}
+
+/// Plays `notes` one after another, each held for `frames` samples.
+fn play_notes(engine: &mut SynthEngine, notes: &[u8], frames: usize) {
+    for &note_number in notes {
+        play(engine, note(note_number, true), frames);
+    }
+}
+
+/// The note of every voice, in voice order.
+fn notes(engine: &SynthEngine) -> Vec<u8> {
+    engine
+        .voices
+        .iter()
+        .map(|voice| voice.note_number)
+        .collect()
+}
+
+/// Plays note 72 on a full engine and returns the note whose voice it took.
+fn stolen_note(engine: &mut SynthEngine) -> u8 {
+    let before = notes(engine);
+    play(engine, note(72, true), FADE_DONE);
+    let after = notes(engine);
+    let taken: Vec<usize> = (0..after.len()).filter(|&i| after[i] == 72).collect();
+    assert_eq!(
+        taken.len(),
+        1,
+        "notes went from {:?} to {:?}",
+        before,
+        after
+    );
+    before[taken[0]]
+}
+
+#[test]
+fn each_strategy_takes_its_voice() {
+    // Oldest: the first note played, whatever state it is in
+    let mut engine = small_engine(4, VoiceStealingStrategy::Oldest);
+    play_notes(&mut engine, &[60, 62, 64, 65], 2400);
+    play(&mut engine, note(64, false), 64);
+    assert_eq!(stolen_note(&mut engine), 60);
+
+    // ReleasedFirst: a released note before an older held one...
+    let mut engine = small_engine(4, VoiceStealingStrategy::ReleasedFirst);
+    play_notes(&mut engine, &[60, 62, 64, 65], 2400);
+    play(&mut engine, note(64, false), 64);
+    assert_eq!(stolen_note(&mut engine), 64);
+    // ...and the oldest when every key is down
+    let mut engine = small_engine(4, VoiceStealingStrategy::ReleasedFirst);
+    play_notes(&mut engine, &[60, 62, 64, 65], 2400);
+    assert_eq!(stolen_note(&mut engine), 60);
+
+    // Quietest: the newest note is still in its attack, well below the others' sustain
+    let mut engine = small_engine(4, VoiceStealingStrategy::Quietest);
+    play_notes(&mut engine, &[60, 62, 64], 2400);
+    play(&mut engine, note(65, true), 64);
+    assert_eq!(stolen_note(&mut engine), 65);
+
+    // SameNote: a voice already playing the note, even with voices free...
+    let mut engine = small_engine(4, VoiceStealingStrategy::SameNote);
+    play_notes(&mut engine, &[60, 62], 2400);
+    let voice = notes(&engine).iter().position(|&n| n == 62).unwrap();
+    play(&mut engine, note(62, true), FADE_DONE);
+    assert_eq!(notes(&engine)[voice], 62);
+    let sounding = engine.voices.iter().filter(|v| !v.is_finished()).count();
+    assert_eq!(sounding, 2, "a second voice started for the same note");
+    // ...and the oldest for a note nobody plays
+    play_notes(&mut engine, &[64, 65], 2400);
+    assert_eq!(stolen_note(&mut engine), 60);
+}
+
+#[test]
+fn protected_notes_survive() {
+    // The oldest note is the lowest, so the next oldest goes instead
+    let mut engine = small_engine(4, VoiceStealingStrategy::Oldest);
+    engine.config.protect_lowest_note = true;
+    play_notes(&mut engine, &[40, 62, 64, 65], 2400);
+    assert_eq!(stolen_note(&mut engine), 62);
+
+    let mut engine = small_engine(4, VoiceStealingStrategy::Oldest);
+    engine.config.protect_highest_note = true;
+    play_notes(&mut engine, &[90, 62, 64, 65], 2400);
+    assert_eq!(stolen_note(&mut engine), 62);
+
+    // Both at once
+    let mut engine = small_engine(4, VoiceStealingStrategy::Oldest);
+    engine.config.protect_lowest_note = true;
+    engine.config.protect_highest_note = true;
+    play_notes(&mut engine, &[40, 90, 64, 65], 2400);
+    assert_eq!(stolen_note(&mut engine), 64);
+
+    // A protected note still goes once it is the only one left to take
+    let mut engine = small_engine(1, VoiceStealingStrategy::Oldest);
+    engine.config.protect_lowest_note = true;
+    play_notes(&mut engine, &[40], 2400);
+    assert_eq!(stolen_note(&mut engine), 40);
+}
+
+#[test]
+fn unison_groups_are_stolen_whole() {
+    let mut engine = small_engine(6, VoiceStealingStrategy::Oldest);
+    engine.set_unison(2, 10.0, 0.5);
+    play_notes(&mut engine, &[60, 62, 64], 2400);
+    let before = notes(&engine);
+    play(&mut engine, note(67, true), FADE_DONE);
+    let after = notes(&engine);
+    // Both voices of the oldest stack move to the new note, and nothing else does
+    let expected: Vec<u8> = before
+        .iter()
+        .map(|&n| if n == 60 { 67 } else { n })
+        .collect();
+    assert_eq!(after, expected);
+
+    // Three voices for the next note: the two oldest stacks are both taken, and the voice
+    // the note doesn't need fades out rather than playing on alone
+    engine.set_unison(3, 10.0, 0.5);
+    play(&mut engine, note(69, true), FADE_DONE);
+    let sounding = |note_number: u8| {
+        let voices = engine.voices.iter();
+        voices
+            .filter(|v| v.note_number == note_number && !v.is_finished())
+            .count()
+    };
+    assert_eq!(sounding(69), 3);
+    assert_eq!(sounding(67), 2);
+    assert_eq!(
+        sounding(62) + sounding(64),
+        0,
+        "a stack was only partly stolen"
+    );
+}
+
+/// The largest jump between neighbouring samples.
+fn largest_step(samples: &[f32]) -> f32 {
+    samples
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).abs())
+        .fold(0.0, f32::max)
+}
+
+#[test]
+fn stolen_voices_fade_out_instead_of_clicking() {
+    // Renders 200 samples after a held note, stealing its voice at `steal` unless it is None
+    let render = |fade_ms: f32, steal: Option<usize>| {
+        let mut engine = small_engine(1, VoiceStealingStrategy::Oldest);
+        engine.config.steal_fade_ms = fade_ms;
+        play_notes(&mut engine, &[60], 4800);
+        if let Some(steal) = steal {
+            let event = NoteEvent::new(72, 100, true, NoteSource::Keyboard).unwrap();
+            engine.queue_event(TimedEvent::at(engine.sample_clock() + steal as u64, event));
+        }
+        let mut output = vec![0.0; 200];
+        engine.process_interleaved(&mut output, 1, SAMPLE_RATE);
+        output
+    };
+
+    // Steal where the old note is at its loudest, so cutting it off would jump the furthest
+    let held = render(5.0, None);
+    let steal = (50..150)
+        .max_by(|&a, &b| held[a].abs().total_cmp(&held[b].abs()))
+        .unwrap();
+    let smooth = largest_step(&held);
+
+    let faded = render(5.0, Some(steal));
+    let step = largest_step(&faded[steal - 1..steal + 20]);
+    assert!(step < smooth * 2.0, "a {} jump at the steal", step);
+
+    // Without a fade the old note stops dead and the new one starts from silence
+    let cut = render(0.0, Some(steal));
+    let step = largest_step(&cut[steal - 1..steal + 20]);
+    assert!(step > smooth * 5.0, "only a {} jump at the steal", step);
+}
@@ -0,0 +1,150 @@
+use rustfmsynth::synth::wavetable::{Wavetable, TABLE_SIZE};
+use rustfmsynth::utils::wav::parse_wav;
+use std::f64::consts::PI;
+
+/// One cycle of `size` samples made of `(harmonic, amplitude)` sines.
+fn cycle(size: usize, harmonics: &[(usize, f64)]) -> Vec<f32> {
+    (0..size)
+        .map(|i| {
+            let t = i as f64 / size as f64;
+            harmonics
+                .iter()
+                .map(|&(harmonic, amplitude)| amplitude * (2.0 * PI * harmonic as f64 * t).sin())
+                .sum::<f64>() as f32
+        })
+        .collect()
+}
+
+/// The samples of one mip level of the first frame.
+fn read_level(table: &Wavetable, level: usize) -> Vec<f32> {
+    (0..TABLE_SIZE)
+        .map(|i| table.sample(i as f32 / TABLE_SIZE as f32, 0.0, level))
+        .collect()
+}
+
+/// Amplitude of `harmonic` in a single cycle.
+fn harmonic_amplitude(cycle: &[f32], harmonic: usize) -> f64 {
+    let n = cycle.len();
+    let (mut re, mut im) = (0.0f64, 0.0f64);
+    for (i, &x) in cycle.iter().enumerate() {
+        let angle = -2.0 * PI * ((harmonic * i) % n) as f64 / n as f64;
+        re += x as f64 * angle.cos();
+        im += x as f64 * angle.sin();
+    }
+    2.0 * (re * re + im * im).sqrt() / n as f64
+}
+
+/// A WAV file with the given format fields and data, plus a `LIST` chunk of odd length
+/// ahead of the data to check that chunks are skipped with their padding.
+fn wav_bytes(format_tag: u16, channels: u16, bits: u16, data: &[u8]) -> Vec<u8> {
+    let block_align = channels * bits / 8;
+    let mut fmt = Vec::new();
+    fmt.extend_from_slice(&format_tag.to_le_bytes());
+    fmt.extend_from_slice(&channels.to_le_bytes());
+    fmt.extend_from_slice(&48000u32.to_le_bytes());
+    fmt.extend_from_slice(&(48000 * block_align as u32).to_le_bytes());
+    fmt.extend_from_slice(&block_align.to_le_bytes());
+    fmt.extend_from_slice(&bits.to_le_bytes());
+
+    let mut body = b"WAVE".to_vec();
+    for (id, chunk) in [(b"fmt ", &fmt[..]), (b"LIST", &b"abc"[..]), (b"data", data)] {
+        body.extend_from_slice(id);
+        body.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+        body.extend_from_slice(chunk);
+        if chunk.len() % 2 == 1 {
+            body.push(0);
+        }
+    }
+    let mut bytes = b"RIFF".to_vec();
+    bytes.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&body);
+    bytes
+}
+
+#[test]
+fn parse_wav_reads_pcm_and_float_samples() {
+    let pcm: Vec<u8> = [16384i16, -32768, 0, 32767]
+        .iter()
+        .flat_map(|s| s.to_le_bytes())
+        .collect();
+    let wav = parse_wav(&wav_bytes(1, 2, 16, &pcm)).unwrap();
+    assert_eq!(wav.sample_rate, 48000);
+    assert_eq!(wav.channels, 2);
+    assert_eq!(wav.samples, vec![0.5, -1.0, 0.0, 32767.0 / 32768.0]);
+    assert_eq!(wav.to_mono(), vec![-0.25, 32767.0 / 65536.0]);
+
+    let pcm24 = [0x00, 0x00, 0xC0, 0xFF, 0xFF, 0x7F];
+    let wav = parse_wav(&wav_bytes(1, 1, 24, &pcm24)).unwrap();
+    assert_eq!(wav.samples, vec![-0.5, 8_388_607.0 / 8_388_608.0]);
+
+    let float: Vec<u8> = [0.25f32, -0.75]
+        .iter()
+        .flat_map(|s| s.to_le_bytes())
+        .collect();
+    let wav = parse_wav(&wav_bytes(3, 1, 32, &float)).unwrap();
+    assert_eq!(wav.samples, vec![0.25, -0.75]);
+}
+
+#[test]
+fn parse_wav_rejects_broken_files() {
+    assert!(parse_wav(b"RIFX\0\0\0\0WAVE").is_err());
+    assert!(parse_wav(b"RIFF").is_err());
+    // Unsupported 12-bit PCM
+    assert!(parse_wav(&wav_bytes(1, 1, 12, &[0, 0])).is_err());
+    assert!(parse_wav(&wav_bytes(1, 0, 16, &[0, 0])).is_err());
+
+    let mut no_data = wav_bytes(1, 1, 16, &[]);
+    no_data.truncate(no_data.len() - 8);
+    assert!(parse_wav(&no_data).is_err());
+}
+
+#[test]
+fn a_sine_frame_comes_back_as_a_sine() {
+    let table = Wavetable::from_frames(vec![cycle(TABLE_SIZE, &[(1, 0.5)])]).unwrap();
+    let expected = cycle(TABLE_SIZE, &[(1, 1.0)]); // Normalized to a peak of 1
+    for level in 0..4 {
+        for (sample, expected) in read_level(&table, level).iter().zip(&expected) {
+            assert!((sample - expected).abs() < 1e-4, "level {}", level);
+        }
+    }
+}
+
+#[test]
+fn mip_levels_keep_only_the_harmonics_below_their_limit() {
+    let table = Wavetable::from_frames(vec![cycle(TABLE_SIZE, &[(1, 0.5), (300, 0.5)])]).unwrap();
+    // Levels hold up to 1023, 511, 255, ... harmonics
+    for (level, keeps_300) in [(0, true), (1, true), (2, false), (3, false)] {
+        let samples = read_level(&table, level);
+        let upper = harmonic_amplitude(&samples, 300) / harmonic_amplitude(&samples, 1);
+        if keeps_300 {
+            assert!((upper - 1.0).abs() < 1e-3, "level {}: {}", level, upper);
+        } else {
+            assert!(upper < 1e-4, "level {}: {}", level, upper);
+        }
+    }
+
+    // At 300 harmonics below Nyquist, level 2 is the richest that doesn't alias
+    assert_eq!(table.mip_level(0.5 / 300.0), 2);
+    assert_eq!(table.mip_level(0.5 / 1023.0), 0);
+    assert_eq!(table.mip_level(0.4), 9);
+}
+
+#[test]
+fn long_cycles_drop_harmonics_the_table_cannot_hold() {
+    // Harmonic 1500 is above the table's Nyquist; decimating it would fold it onto 548
+    let size = TABLE_SIZE * 4;
+    let table = Wavetable::from_frames(vec![cycle(size, &[(1, 0.5), (1500, 0.5)])]).unwrap();
+    let samples = read_level(&table, 0);
+    let folded = harmonic_amplitude(&samples, TABLE_SIZE - 1500) / harmonic_amplitude(&samples, 1);
+    assert!(folded < 1e-3, "aliased harmonic at {}", folded);
+
+    // The same from a length that isn't a power of two, keeping what the table can hold
+    let harmonics = [(1, 0.5), (700, 0.5), (1200, 0.5)];
+    let table = Wavetable::from_frames(vec![cycle(3000, &harmonics)]).unwrap();
+    let samples = read_level(&table, 0);
+    let fundamental = harmonic_amplitude(&samples, 1);
+    let kept = harmonic_amplitude(&samples, 700) / fundamental;
+    assert!(kept > 0.5, "harmonic 700 at {}", kept);
+    let folded = harmonic_amplitude(&samples, TABLE_SIZE - 1200) / fundamental;
+    assert!(folded < 1e-3, "aliased harmonic at {}", folded);
+}